@@ -0,0 +1,1882 @@
+//! The built-in commands of the shell. Each one parses its arguments and
+//! calls the function in `commands` which does the actual work.
+
+use std::path::{Path, PathBuf};
+
+use crate::checksum::HashAlgorithm;
+use crate::command::*;
+use crate::commands::*;
+use crate::error::ShellError;
+use crate::help::{help, type_of, which};
+use crate::jobs::parse_job_id;
+use crate::{CommandOutput, OutputFormat, ShellCommand};
+
+/// Registers every built-in, in the order `help` lists them.
+pub fn register_all(registry: &mut Registry) {
+    registry.register::<More>();
+    registry.register::<Cat>();
+    registry.register::<Ls>();
+    registry.register::<Cd>();
+    registry.register::<FindFile>();
+    registry.register::<FindString>();
+    registry.register::<SubsString>();
+    registry.register::<Head>();
+    registry.register::<Tail>();
+    registry.register::<Wc>();
+    registry.register::<Sort>();
+    registry.register::<Uniq>();
+    registry.register::<Cut>();
+    registry.register::<Tr>();
+    registry.register::<Cp>();
+    registry.register::<Mv>();
+    registry.register::<Rm>();
+    registry.register::<Mkdir>();
+    registry.register::<Touch>();
+    registry.register::<Tree>();
+    registry.register::<Du>();
+    registry.register::<Diff>();
+    registry.register::<Hexdump>();
+    registry.register::<Xxd>();
+    registry.register::<Stat>();
+    registry.register::<FileType>();
+    registry.register::<Sha256sum>();
+    registry.register::<Md5sum>();
+    registry.register::<Echo>();
+    registry.register::<Printf>();
+    registry.register::<Tee>();
+    registry.register::<SetOutputFormat>();
+    registry.register::<SetDryRun>();
+    registry.register::<SetTrash>();
+    registry.register::<Restore>();
+    registry.register::<Help>();
+    registry.register::<Type>();
+    registry.register::<Which>();
+    registry.register::<Jobs>();
+    registry.register::<Fg>();
+    registry.register::<Bg>();
+    registry.register::<Wait>();
+    registry.register::<Kill>();
+    registry.register::<Exit>();
+}
+
+/// Commands reading files, or pipe input when given none.
+fn files_or_pipe(files: &[PathBuf]) -> PipeInput {
+    if files.is_empty() {
+        PipeInput::Required
+    } else {
+        PipeInput::Unexpected
+    }
+}
+
+/// Print the contents of file to screen.
+#[derive(Debug, Clone)]
+pub struct More(PathBuf);
+
+impl Command for More {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "more",
+            aliases: &[],
+            args: "<file>",
+            summary: "Show a file, paged when printing to a terminal",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        match args {
+            [path] => Some(More(PathBuf::from(path))),
+            _ => None,
+        }
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        more_or_hexdump(context.session.resolve(&self.0))
+    }
+
+    fn presentation(&self) -> Presentation {
+        Presentation::Paged(self.0.display().to_string())
+    }
+}
+
+/// Takes 1 or more files and concats their contents.
+#[derive(Debug, Clone)]
+pub struct Cat(Vec<PathBuf>);
+
+impl Command for Cat {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "cat",
+            aliases: &[],
+            args: "<files...>",
+            summary: "Concatenate files",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        if args.is_empty() {
+            return None;
+        }
+        Some(Cat(args.iter().map(PathBuf::from).collect()))
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        cat_files(&context.resolve(&self.0), context.session.token())
+    }
+}
+
+/// Print all entries in a directory.
+#[derive(Debug, Clone)]
+pub struct Ls(PathBuf);
+
+impl Command for Ls {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "ls",
+            aliases: &[],
+            args: "[dir]",
+            summary: "List the entries of a directory",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        match args {
+            [] => Some(Ls(PathBuf::from("."))),
+            [path] => Some(Ls(PathBuf::from(path))),
+            _ => None,
+        }
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        ls_dir(&context.session.resolve(&self.0))
+    }
+}
+
+/// Change current working directory.
+#[derive(Debug, Clone)]
+pub struct Cd(PathBuf);
+
+impl Command for Cd {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "cd",
+            aliases: &[],
+            args: "<dir>",
+            summary: "Change the current directory",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        match args {
+            [path] => Some(Cd(PathBuf::from(path))),
+            _ => None,
+        }
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        let dir = cd(&context.session.resolve(&self.0))?;
+        context.session.set_cwd(dir);
+        Ok(CommandOutput::None)
+    }
+}
+
+/// Look for file name recursively under directory.
+/// FindFile(name, dir)
+#[derive(Debug, Clone)]
+pub struct FindFile(String, PathBuf);
+
+impl Command for FindFile {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "find-file",
+            aliases: &[],
+            args: "<name> <dir>",
+            summary: "Find files called name under dir",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        match args {
+            [name, dir] => Some(FindFile(name.to_string(), PathBuf::from(dir))),
+            _ => None,
+        }
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        find_file(
+            context.session.resolve(&self.1),
+            &self.0,
+            context.session.token(),
+        )
+    }
+}
+
+/// Returns all lines that match the second string, in the
+/// first string.
+/// If Option is None, this command is expecting pipe input.
+/// FindString(content, matcher)
+#[derive(Debug, Clone)]
+pub struct FindString(Option<String>, String);
+
+impl Command for FindString {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "find-string",
+            aliases: &[],
+            args: "[--lossy] [content] <string>",
+            summary: "Keep the lines of content or pipe input containing string",
+            flags: &[("--lossy", "Replace invalid UTF-8 in pipe input")],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        match args {
+            [content, string] => Some(FindString(Some(content.to_string()), string.to_string())),
+            [string] => Some(FindString(None, string.to_string())),
+            _ => None,
+        }
+    }
+
+    fn pipe_input(&self) -> PipeInput {
+        match self.0 {
+            Some(_) => PipeInput::Unexpected,
+            None => PipeInput::Required,
+        }
+    }
+
+    fn accepts_lossy(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        match &self.0 {
+            Some(content) => find_string(content, &self.1, context.session.token()),
+            None => {
+                let content = context.text_input(self)?;
+                find_string(&content, &self.1, context.session.token())
+            }
+        }
+    }
+}
+
+/// Replaces all instances of `from` with `to` in contents.
+/// If Option is None, this command is expecting pipe input.
+/// SubsString(contents, from, to)
+#[derive(Debug, Clone)]
+pub struct SubsString(Option<String>, String, String);
+
+impl Command for SubsString {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "subs-string",
+            aliases: &[],
+            args: "[--lossy] [content] <from> <to>",
+            summary: "Replace from with to in content or pipe input",
+            flags: &[("--lossy", "Replace invalid UTF-8 in pipe input")],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        match args {
+            [content, from, to] => Some(SubsString(
+                Some(content.to_string()),
+                from.to_string(),
+                to.to_string(),
+            )),
+            [from, to] => Some(SubsString(None, from.to_string(), to.to_string())),
+            _ => None,
+        }
+    }
+
+    fn pipe_input(&self) -> PipeInput {
+        match self.0 {
+            Some(_) => PipeInput::Unexpected,
+            None => PipeInput::Required,
+        }
+    }
+
+    fn accepts_lossy(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        match &self.0 {
+            Some(content) => Ok(subs_string(content, &self.1, &self.2)),
+            None => Ok(subs_string(&context.text_input(self)?, &self.1, &self.2)),
+        }
+    }
+}
+
+/// First `n` lines of each file.
+/// If there are no files, this command is expecting pipe input.
+/// Head(files, n)
+#[derive(Debug, Clone)]
+pub struct Head(Vec<PathBuf>, usize);
+
+impl Command for Head {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "head",
+            aliases: &[],
+            args: "[-n count] [files...]",
+            summary: "First lines of files or pipe input",
+            flags: &[("-n count", "Number of lines, 10 by default")],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        let (files, n, _) = parse_head_tail(args, false)?;
+        Some(Head(files, n))
+    }
+
+    fn pipe_input(&self) -> PipeInput {
+        files_or_pipe(&self.0)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        if self.0.is_empty() {
+            Ok(head_lines(&context.text_input(self)?, self.1))
+        } else {
+            head_files(&context.resolve(&self.0), self.1)
+        }
+    }
+}
+
+/// Last `n` lines of each file, then keeps printing lines appended to
+/// the files if `follow` is set.
+/// If there are no files, this command is expecting pipe input.
+/// Tail(files, n, follow)
+#[derive(Debug, Clone)]
+pub struct Tail(Vec<PathBuf>, usize, bool);
+
+impl Command for Tail {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "tail",
+            aliases: &[],
+            args: "[-n count] [-f] [files...]",
+            summary: "Last lines of files or pipe input",
+            flags: &[
+                ("-n count", "Number of lines, 10 by default"),
+                ("-f", "Keep printing appended lines, on the terminal"),
+            ],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        let (files, n, follow) = parse_head_tail(args, true)?;
+        if follow && files.is_empty() {
+            return None;
+        }
+        Some(Tail(files, n, follow))
+    }
+
+    fn pipe_input(&self) -> PipeInput {
+        files_or_pipe(&self.0)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        match self {
+            Tail(files, n, _) if files.is_empty() => Ok(tail_lines(&context.text_input(self)?, *n)),
+            Tail(files, n, false) => tail_files(&context.resolve(files), *n),
+            // Appended lines are printed as they come, which a pipe or a
+            // file cannot wait for.
+            Tail(_, _, true) if context.session.is_captured() => Err(ShellError::NoInputForPipe(
+                ShellCommand::from(self as &dyn Command),
+            )),
+            Tail(files, n, true) => {
+                let format = context.session.settings.output_format;
+                let sink = context.session.output().cloned();
+                let print = |output: CommandOutput| match &sink {
+                    Some(sink) => sink(Ok(output), format),
+                    None => output.print_as(format),
+                };
+                follow_files(&context.resolve(files), *n, print, context.session.token())
+            }
+        }
+    }
+}
+
+/// Parses the arguments of `head` and `tail`: `[-n count] [-f] [files...]`,
+/// where `-f` is only accepted by `tail`.
+fn parse_head_tail(args: &[&str], tail: bool) -> Option<(Vec<PathBuf>, usize, bool)> {
+    let mut n = 10;
+    let mut follow = false;
+    let mut files = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "-n" => n = args.next()?.parse().ok()?,
+            "-f" if tail => follow = true,
+            arg if arg.starts_with("-n") => n = arg[2..].parse().ok()?,
+            arg if arg.starts_with('-') => return None,
+            file => files.push(PathBuf::from(file)),
+        }
+    }
+    Some((files, n, follow))
+}
+
+/// Count lines, words, bytes and characters of each file.
+/// If there are no files, this command is expecting pipe input.
+/// Wc(files, counts)
+#[derive(Debug, Clone)]
+pub struct Wc(Vec<PathBuf>, WcOptions);
+
+impl Command for Wc {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "wc",
+            aliases: &[],
+            args: "[-l] [-w] [-c] [-m] [files...]",
+            summary: "Count lines, words, bytes and characters",
+            flags: &[
+                ("-l", "Count lines"),
+                ("-w", "Count words"),
+                ("-c", "Count bytes"),
+                ("-m", "Count characters"),
+            ],
+        }
+    }
+
+    /// Parses `wc [-l] [-w] [-c] [-m] [files...]`.
+    fn parse(args: &[&str]) -> Option<Self> {
+        let mut options = WcOptions::default();
+        let mut files = vec![];
+
+        for arg in args {
+            match arg.strip_prefix('-') {
+                Some(flags) => {
+                    for flag in flags.chars() {
+                        match flag {
+                            'l' => options.lines = true,
+                            'w' => options.words = true,
+                            'c' => options.bytes = true,
+                            'm' => options.chars = true,
+                            _ => return None,
+                        }
+                    }
+                }
+                None => files.push(PathBuf::from(arg)),
+            }
+        }
+        Some(Wc(files, options))
+    }
+
+    fn pipe_input(&self) -> PipeInput {
+        files_or_pipe(&self.0)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        if self.0.is_empty() {
+            Ok(wc(&context.text_input(self)?, &self.1))
+        } else {
+            wc_files(&context.resolve(&self.0), &self.1)
+        }
+    }
+}
+
+/// Sort the lines of the files.
+/// If there are no files, this command is expecting pipe input.
+/// Sort(files, options)
+#[derive(Debug, Clone)]
+pub struct Sort(Vec<PathBuf>, SortOptions);
+
+impl Command for Sort {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "sort",
+            aliases: &[],
+            args: "[-n] [-r] [-u] [-k field] [files...]",
+            summary: "Sort lines",
+            flags: &[
+                ("-n", "Compare numbers at the start of lines"),
+                ("-r", "Reverse the order"),
+                ("-u", "Keep only the first of equal lines"),
+                ("-k field", "Sort on a whitespace separated field"),
+            ],
+        }
+    }
+
+    /// Parses `sort [-n] [-r] [-u] [-k field] [files...]`.
+    fn parse(args: &[&str]) -> Option<Self> {
+        let mut options = SortOptions::default();
+        let mut files = vec![];
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix('-') {
+                Some("k") => options.key = Some(parse_position(args.next()?)?),
+                Some(flags) if flags.starts_with('k') => {
+                    options.key = Some(parse_position(&flags[1..])?)
+                }
+                Some(flags) => {
+                    for flag in flags.chars() {
+                        match flag {
+                            'n' => options.numeric = true,
+                            'r' => options.reverse = true,
+                            'u' => options.unique = true,
+                            _ => return None,
+                        }
+                    }
+                }
+                None => files.push(PathBuf::from(arg)),
+            }
+        }
+        Some(Sort(files, options))
+    }
+
+    fn pipe_input(&self) -> PipeInput {
+        files_or_pipe(&self.0)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        let content = if self.0.is_empty() {
+            context.text_input(self)?
+        } else {
+            cat_to_string(&context.resolve(&self.0))?
+        };
+        Ok(sort_lines(&content, &self.1))
+    }
+}
+
+/// Collapse repeated lines, counting them if `count` is set.
+/// If there are no files, this command is expecting pipe input.
+/// Uniq(files, count)
+#[derive(Debug, Clone)]
+pub struct Uniq(Vec<PathBuf>, bool);
+
+impl Command for Uniq {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "uniq",
+            aliases: &[],
+            args: "[-c] [files...]",
+            summary: "Collapse repeated lines",
+            flags: &[("-c", "Count the repeated lines")],
+        }
+    }
+
+    /// Parses `uniq [-c] [files...]`.
+    fn parse(args: &[&str]) -> Option<Self> {
+        let mut count = false;
+        let mut files = vec![];
+
+        for arg in args {
+            match *arg {
+                "-c" => count = true,
+                arg if arg.starts_with('-') => return None,
+                file => files.push(PathBuf::from(file)),
+            }
+        }
+        Some(Uniq(files, count))
+    }
+
+    fn pipe_input(&self) -> PipeInput {
+        files_or_pipe(&self.0)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        let content = if self.0.is_empty() {
+            context.text_input(self)?
+        } else {
+            cat_to_string(&context.resolve(&self.0))?
+        };
+        Ok(uniq_lines(&content, self.1))
+    }
+}
+
+/// Keep some of the fields of each line.
+/// If there are no files, this command is expecting pipe input.
+/// Cut(files, delimiter, field ranges)
+#[derive(Debug, Clone)]
+pub struct Cut(Vec<PathBuf>, char, Vec<(usize, usize)>);
+
+impl Command for Cut {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "cut",
+            aliases: &[],
+            args: "[-d delimiter] -f fields [files...]",
+            summary: "Keep some fields of each line",
+            flags: &[
+                ("-d delimiter", "Field delimiter, tab by default"),
+                ("-f fields", "Fields to keep, e.g. 1,3-4"),
+            ],
+        }
+    }
+
+    /// Parses `cut [-d delimiter] -f fields [files...]`. The delimiter
+    /// defaults to a tab and fields are a comma separated list of positions
+    /// or ranges, e.g. `1,3-5,7-`.
+    fn parse(args: &[&str]) -> Option<Self> {
+        let mut delimiter = '\t';
+        let mut fields = None;
+        let mut files = vec![];
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let (flag, value) = match *arg {
+                "-d" | "-f" => (*arg, unescape(args.next()?)),
+                arg if arg.starts_with("-d") || arg.starts_with("-f") => {
+                    (&arg[..2], unescape(&arg[2..]))
+                }
+                arg if arg.starts_with('-') => return None,
+                file => {
+                    files.push(PathBuf::from(file));
+                    continue;
+                }
+            };
+
+            if flag == "-f" {
+                fields = Some(parse_field_ranges(&value)?);
+            } else {
+                let mut chars = value.chars();
+                delimiter = chars.next()?;
+                if chars.next().is_some() {
+                    return None;
+                }
+            }
+        }
+        Some(Cut(files, delimiter, fields?))
+    }
+
+    fn pipe_input(&self) -> PipeInput {
+        files_or_pipe(&self.0)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        let content = if self.0.is_empty() {
+            context.text_input(self)?
+        } else {
+            cat_to_string(&context.resolve(&self.0))?
+        };
+        Ok(cut_fields(&content, self.1, &self.2))
+    }
+}
+
+/// Parses a `cut` field list such as `1,3-5,7-` into inclusive ranges.
+fn parse_field_ranges(list: &str) -> Option<Vec<(usize, usize)>> {
+    list.split(',')
+        .map(|range| match range.split_once('-') {
+            Some((from, "")) => Some((parse_position(from)?, usize::MAX)),
+            Some(("", to)) => Some((1, parse_position(to)?)),
+            Some((from, to)) => Some((parse_position(from)?, parse_position(to)?)),
+            None => {
+                let position = parse_position(range)?;
+                Some((position, position))
+            }
+        })
+        .collect()
+}
+
+/// Parses a field position, which start from 1.
+fn parse_position(s: &str) -> Option<usize> {
+    s.parse().ok().filter(|&position| position > 0)
+}
+
+/// Translate characters in `from` to the ones in `to`, or delete them
+/// if `to` is None. Only takes input from pipe.
+/// Tr(from, to)
+#[derive(Debug, Clone)]
+pub struct Tr(String, Option<String>);
+
+impl Command for Tr {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "tr",
+            aliases: &[],
+            args: "<from> <to> | -d <chars>",
+            summary: "Translate or delete characters of pipe input",
+            flags: &[("-d", "Delete the characters")],
+        }
+    }
+
+    /// Parses `tr from to` and `tr -d chars`.
+    fn parse(args: &[&str]) -> Option<Self> {
+        match args {
+            ["-d", from] => Some(Tr(unescape(from), None)),
+            [from, to] if !from.starts_with('-') => Some(Tr(unescape(from), Some(unescape(to)))),
+            _ => None,
+        }
+    }
+
+    fn pipe_input(&self) -> PipeInput {
+        PipeInput::Required
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        let input = context.text_input(self)?;
+        Ok(translate(&input, &self.0, self.1.as_deref()))
+    }
+}
+
+/// Replaces escape sequences in command arguments: `\n`, `\t`, `\\` and
+/// `\s` for a space, since spaces always separate arguments.
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some(escaped @ ('n' | 't' | 's' | '\\'))) => {
+                chars.next();
+                unescaped.push(match escaped {
+                    'n' => '\n',
+                    't' => '\t',
+                    's' => ' ',
+                    _ => '\\',
+                });
+            }
+            (c, _) => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Splits the arguments of the file manipulation commands into the flags
+/// given, all of which must be in `allowed_flags`, and at least one path.
+fn parse_paths(args: &[&str], allowed_flags: &str) -> Option<(String, Vec<PathBuf>)> {
+    let mut flags = String::new();
+    let mut paths = vec![];
+
+    for arg in args {
+        match *arg {
+            arg if arg.starts_with('-') && arg.len() > 1 => {
+                if !arg[1..].chars().all(|flag| allowed_flags.contains(flag)) {
+                    return None;
+                }
+                flags.push_str(&arg[1..]);
+            }
+            path => paths.push(PathBuf::from(path)),
+        }
+    }
+    if paths.is_empty() {
+        return None;
+    }
+    Some((flags, paths))
+}
+
+/// Copy files, and directories if `recursive`, to a file or into a
+/// directory. Sources may contain wildcards.
+/// Cp(sources, destination, recursive)
+#[derive(Debug, Clone)]
+pub struct Cp(Vec<PathBuf>, PathBuf, bool);
+
+impl Command for Cp {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "cp",
+            aliases: &[],
+            args: "[-r] <sources...> <destination>",
+            summary: "Copy files",
+            flags: &[("-r", "Copy directories and their contents")],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        let (flags, mut sources) = parse_paths(args, "r")?;
+        let dest = sources.pop()?;
+        if sources.is_empty() {
+            return None;
+        }
+        Some(Cp(sources, dest, flags.contains('r')))
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        let dest = context.session.resolve(&self.1);
+        let mode = &context.session.settings.change_mode;
+        cp(&context.resolve(&self.0), &dest, self.2, mode)
+    }
+}
+
+/// Move files to a new name or into a directory.
+/// Sources may contain wildcards.
+/// Mv(sources, destination)
+#[derive(Debug, Clone)]
+pub struct Mv(Vec<PathBuf>, PathBuf);
+
+impl Command for Mv {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "mv",
+            aliases: &[],
+            args: "<sources...> <destination>",
+            summary: "Move or rename files",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        let (_, mut sources) = parse_paths(args, "")?;
+        let dest = sources.pop()?;
+        if sources.is_empty() {
+            return None;
+        }
+        Some(Mv(sources, dest))
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        let dest = context.session.resolve(&self.1);
+        let mode = &context.session.settings.change_mode;
+        mv(&context.resolve(&self.0), &dest, mode)
+    }
+}
+
+/// Remove files and directories. Paths may contain wildcards.
+#[derive(Debug, Clone)]
+pub struct Rm(Vec<PathBuf>, RmOptions);
+
+impl Command for Rm {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "rm",
+            aliases: &[],
+            args: "[-r] [-f] [--no-preserve-root] <paths...>",
+            summary: "Remove files, to the trash in trash mode",
+            flags: &[
+                ("-r", "Remove directories and their contents"),
+                ("-f", "Ignore files that do not exist"),
+                (
+                    "--no-preserve-root",
+                    "Allow removing / or the home directory",
+                ),
+            ],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        let no_preserve_root = args.contains(&"--no-preserve-root");
+        let args: Vec<&str> = args
+            .iter()
+            .copied()
+            .filter(|arg| *arg != "--no-preserve-root")
+            .collect();
+        let (flags, paths) = parse_paths(&args, "rf")?;
+        let options = RmOptions {
+            recursive: flags.contains('r'),
+            force: flags.contains('f'),
+            no_preserve_root,
+        };
+        Some(Rm(paths, options))
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        let mode = &context.session.settings.change_mode;
+        rm(&context.resolve(&self.0), &self.1, mode)
+    }
+}
+
+/// Create directories, and their parents if `parents`.
+/// Mkdir(dirs, parents)
+#[derive(Debug, Clone)]
+pub struct Mkdir(Vec<PathBuf>, bool);
+
+impl Command for Mkdir {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "mkdir",
+            aliases: &[],
+            args: "[-p] <dirs...>",
+            summary: "Create directories",
+            flags: &[("-p", "Create missing parents too")],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        let (flags, paths) = parse_paths(args, "p")?;
+        Some(Mkdir(paths, flags.contains('p')))
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        let mode = &context.session.settings.change_mode;
+        mkdir(&context.resolve(&self.0), self.1, mode)
+    }
+}
+
+/// Create empty files or update their modification time.
+/// Paths may contain wildcards.
+#[derive(Debug, Clone)]
+pub struct Touch(Vec<PathBuf>);
+
+impl Command for Touch {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "touch",
+            aliases: &[],
+            args: "<files...>",
+            summary: "Create files or update their modification time",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        let (_, paths) = parse_paths(args, "")?;
+        Some(Touch(paths))
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        touch(
+            &context.resolve(&self.0),
+            &context.session.settings.change_mode,
+        )
+    }
+}
+
+/// Draw the hierarchy under a directory, at most `depth` levels deep.
+/// Tree(dir, depth)
+#[derive(Debug, Clone)]
+pub struct Tree(PathBuf, Option<usize>);
+
+impl Command for Tree {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "tree",
+            aliases: &[],
+            args: "[-L depth] [dir]",
+            summary: "Draw the hierarchy under a directory",
+            flags: &[("-L depth", "Go at most depth levels deep")],
+        }
+    }
+
+    /// Parses `tree [-L depth] [path]`.
+    fn parse(args: &[&str]) -> Option<Self> {
+        let (depth, args) = match args {
+            ["-L", depth, rest @ ..] => (Some(parse_position(depth)?), rest),
+            args => (None, args),
+        };
+        match args {
+            [] => Some(Tree(PathBuf::from("."), depth)),
+            [path] if !path.starts_with('-') => Some(Tree(PathBuf::from(path), depth)),
+            _ => None,
+        }
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        tree(
+            &context.session.resolve(&self.0),
+            self.1,
+            context.session.token(),
+        )
+    }
+}
+
+/// Sum file sizes per directory.
+#[derive(Debug, Clone)]
+pub struct Du(PathBuf, DuOptions);
+
+impl Command for Du {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "du",
+            aliases: &[],
+            args: "[-s] [-h] [path]",
+            summary: "Sum file sizes per directory",
+            flags: &[
+                ("-s", "Only show the total"),
+                ("-h", "Show sizes with units"),
+            ],
+        }
+    }
+
+    /// Parses `du [-s] [-h] [path]`.
+    fn parse(args: &[&str]) -> Option<Self> {
+        let mut options = DuOptions::default();
+        let mut paths = vec![];
+
+        for arg in args {
+            match arg.strip_prefix('-') {
+                Some(flags) => {
+                    for flag in flags.chars() {
+                        match flag {
+                            's' => options.summarize = true,
+                            'h' => options.human = true,
+                            _ => return None,
+                        }
+                    }
+                }
+                None => paths.push(PathBuf::from(arg)),
+            }
+        }
+        match paths.as_slice() {
+            [] => Some(Du(PathBuf::from("."), options)),
+            [path] => Some(Du(path.clone(), options)),
+            _ => None,
+        }
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        du(
+            &context.session.resolve(&self.0),
+            &self.1,
+            context.session.token(),
+        )
+    }
+}
+
+/// Compare two files line by line, printing the differences in unified
+/// format with `context` lines around them. A file named `-` stands
+/// for pipe input, only one of the files can be. The output of other
+/// commands is compared through `<(command)` operands, which
+/// `Session::expand` replaces by a path to read it from.
+/// Diff(old, new, context)
+#[derive(Debug, Clone)]
+pub struct Diff(PathBuf, PathBuf, usize);
+
+impl Command for Diff {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "diff",
+            aliases: &[],
+            args: "[-u] [-U context] <old> <new>",
+            summary: "Compare files or <(command) outputs line by line",
+            flags: &[
+                ("-u", "Unified output, always on"),
+                (
+                    "-U context",
+                    "Lines of context around changes, 3 by default",
+                ),
+            ],
+        }
+    }
+
+    /// Parses `diff [-u] [-U context] old new`.
+    fn parse(args: &[&str]) -> Option<Self> {
+        let mut context = 3;
+        let mut files = vec![];
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match *arg {
+                "-u" => {}
+                "-U" => context = args.next()?.parse().ok()?,
+                "-" => files.push(PathBuf::from(arg)),
+                arg if arg.starts_with('-') => return None,
+                arg => files.push(PathBuf::from(arg)),
+            }
+        }
+        match files.as_slice() {
+            [old, new] if old == Path::new("-") && new == Path::new("-") => None,
+            [old, new] => Some(Diff(old.clone(), new.clone(), context)),
+            _ => None,
+        }
+    }
+
+    fn pipe_input(&self) -> PipeInput {
+        if self.0 == Path::new("-") || self.1 == Path::new("-") {
+            PipeInput::Required
+        } else {
+            PipeInput::Unexpected
+        }
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        let input = match self.pipe_input() {
+            PipeInput::Required => Some(context.text_input(self)?),
+            _ => None,
+        };
+        // `-` stays as it is, it is not a file.
+        let resolve = |path: &PathBuf| match path.to_str() {
+            Some("-") => path.clone(),
+            _ => context.session.resolve(path),
+        };
+        diff_files(
+            &resolve(&self.0),
+            &resolve(&self.1),
+            input.as_deref(),
+            self.2,
+        )
+    }
+
+    /// `diff` follows the usual convention of 1 when there are
+    /// differences and 2 when it failed.
+    fn status(&self, result: &Result<CommandOutput, ShellError>) -> i32 {
+        match result {
+            Err(_) => 2,
+            Ok(CommandOutput::None) => 0,
+            Ok(_) => 1,
+        }
+    }
+
+    fn presentation(&self) -> Presentation {
+        Presentation::Colored(color_diff_line)
+    }
+}
+
+/// Show bytes as offset, hex and ASCII columns, or turn such a dump
+/// back into bytes.
+/// If there are no files, this command is expecting pipe input.
+/// Hexdump(files, options)
+#[derive(Debug, Clone)]
+pub struct Hexdump(Vec<PathBuf>, HexdumpOptions);
+
+impl Command for Hexdump {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "hexdump",
+            aliases: &["hd"],
+            args: "[-n length] [-s skip] [-r] [files...]",
+            summary: "Show bytes as hex and ASCII",
+            flags: &[
+                ("-n length", "Show at most length bytes"),
+                ("-s skip", "Start after skip bytes"),
+                ("-r", "Turn a hexdump back into bytes"),
+            ],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        let (files, options) = parse_hexdump(args, "-n")?;
+        Some(Hexdump(files, options))
+    }
+
+    fn pipe_input(&self) -> PipeInput {
+        files_or_pipe(&self.0)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        if self.0.is_empty() {
+            hexdump_bytes(&context.bytes_input(self)?, &self.1)
+        } else {
+            hexdump_files(&context.resolve(&self.0), &self.1)
+        }
+    }
+}
+
+/// `hexdump` with the layout of xxd: bytes in groups of two and no `|`
+/// around the ASCII column.
+/// If there are no files, this command is expecting pipe input.
+/// Xxd(files, options)
+#[derive(Debug, Clone)]
+pub struct Xxd(Vec<PathBuf>, HexdumpOptions);
+
+impl Command for Xxd {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "xxd",
+            aliases: &[],
+            args: "[-l length] [-s skip] [-r] [files...]",
+            summary: "Show bytes as hex and ASCII, like xxd",
+            flags: &[
+                ("-l length", "Show at most length bytes"),
+                ("-s skip", "Start after skip bytes"),
+                ("-r", "Turn a hexdump back into bytes"),
+            ],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        let (files, options) = parse_hexdump(args, "-l")?;
+        Some(Xxd(
+            files,
+            HexdumpOptions {
+                xxd: true,
+                ..options
+            },
+        ))
+    }
+
+    fn pipe_input(&self) -> PipeInput {
+        files_or_pipe(&self.0)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        if self.0.is_empty() {
+            hexdump_bytes(&context.bytes_input(self)?, &self.1)
+        } else {
+            hexdump_files(&context.resolve(&self.0), &self.1)
+        }
+    }
+}
+
+/// Parses the arguments of `hexdump` and `xxd`:
+/// `[<length_flag> length] [-s skip] [files...]` or `-r [files...]`.
+fn parse_hexdump(args: &[&str], length_flag: &str) -> Option<(Vec<PathBuf>, HexdumpOptions)> {
+    let mut options = HexdumpOptions::default();
+    let mut files = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            flag if flag == length_flag => options.length = Some(args.next()?.parse().ok()?),
+            "-s" => options.skip = args.next()?.parse().ok()?,
+            "-r" => options.reverse = true,
+            arg if arg.starts_with('-') => return None,
+            file => files.push(PathBuf::from(file)),
+        }
+    }
+    if options.reverse && (options.skip > 0 || options.length.is_some()) {
+        return None;
+    }
+    Some((files, options))
+}
+
+/// Parses the arguments of the commands inspecting files: paths and no
+/// flags.
+fn parse_inspect(args: &[&str]) -> Option<Vec<PathBuf>> {
+    if args.iter().any(|arg| arg.starts_with('-')) {
+        return None;
+    }
+    Some(args.iter().map(PathBuf::from).collect())
+}
+
+/// Show metadata of files: size, permissions, owner, timestamps...
+#[derive(Debug, Clone)]
+pub struct Stat(Vec<PathBuf>);
+
+impl Command for Stat {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "stat",
+            aliases: &[],
+            args: "<paths...>",
+            summary: "Show size, permissions, owner and times of files",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        parse_inspect(args)
+            .filter(|paths| !paths.is_empty())
+            .map(Stat)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        stat(&context.resolve(&self.0))
+    }
+}
+
+/// Guess the kind of data in files from their contents.
+#[derive(Debug, Clone)]
+pub struct FileType(Vec<PathBuf>);
+
+impl Command for FileType {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "file",
+            aliases: &[],
+            args: "<paths...>",
+            summary: "Guess the kind of data in files",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        parse_inspect(args)
+            .filter(|paths| !paths.is_empty())
+            .map(FileType)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        file_types(&context.resolve(&self.0))
+    }
+}
+
+/// Hash the contents of each file with SHA-256.
+/// If there are no files, this command is expecting pipe input.
+#[derive(Debug, Clone)]
+pub struct Sha256sum(Vec<PathBuf>);
+
+impl Command for Sha256sum {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "sha256sum",
+            aliases: &[],
+            args: "[files...]",
+            summary: "SHA-256 hash of files or pipe input",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        parse_inspect(args).map(Sha256sum)
+    }
+
+    fn pipe_input(&self) -> PipeInput {
+        files_or_pipe(&self.0)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        if self.0.is_empty() {
+            Ok(checksum(&context.bytes_input(self)?, HashAlgorithm::Sha256))
+        } else {
+            checksum_files(&context.resolve(&self.0), HashAlgorithm::Sha256)
+        }
+    }
+}
+
+/// Hash the contents of each file with MD5.
+/// If there are no files, this command is expecting pipe input.
+#[derive(Debug, Clone)]
+pub struct Md5sum(Vec<PathBuf>);
+
+impl Command for Md5sum {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "md5sum",
+            aliases: &[],
+            args: "[files...]",
+            summary: "MD5 hash of files or pipe input",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        parse_inspect(args).map(Md5sum)
+    }
+
+    fn pipe_input(&self) -> PipeInput {
+        files_or_pipe(&self.0)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        if self.0.is_empty() {
+            Ok(checksum(&context.bytes_input(self)?, HashAlgorithm::Md5))
+        } else {
+            checksum_files(&context.resolve(&self.0), HashAlgorithm::Md5)
+        }
+    }
+}
+
+/// Output text given on the command line, to print or feed a pipe.
+#[derive(Debug, Clone)]
+pub struct Echo(pub String);
+
+impl Command for Echo {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "echo",
+            aliases: &[],
+            args: "[-n] [-e] [words...]",
+            summary: "Output words",
+            flags: &[
+                ("-n", "No newline at the end"),
+                ("-e", "Replace escape sequences"),
+            ],
+        }
+    }
+
+    /// Parses `echo [-n] [-e] [words...]`. The words are printed separated
+    /// by spaces and followed by a newline unless `-n`. With `-e` escape
+    /// sequences are replaced, see `expand_escapes`.
+    fn parse(args: &[&str]) -> Option<Self> {
+        let mut words: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        let mut newline = true;
+        let mut escapes = false;
+
+        // Flags only count before the first word, like other shells.
+        let flags = words
+            .iter()
+            .take_while(|w| {
+                w.len() > 1 && w.starts_with('-') && w[1..].chars().all(|c| "ne".contains(c))
+            })
+            .count();
+        for flag in words.drain(..flags) {
+            newline &= !flag.contains('n');
+            escapes |= flag.contains('e');
+        }
+
+        let mut text = words.join(" ");
+        if escapes {
+            let (expanded, stop) = expand_escapes(&text);
+            text = expanded;
+            newline &= !stop;
+        }
+        if newline {
+            text.push('\n');
+        }
+        Some(Echo(text))
+    }
+
+    fn pipe_input(&self) -> PipeInput {
+        PipeInput::Unexpected
+    }
+
+    fn execute(&self, _: &mut Context) -> Result<CommandOutput, ShellError> {
+        Ok(CommandOutput::Single(self.0.clone()))
+    }
+}
+
+/// Output arguments formatted according to a format string.
+/// Printf(format, args)
+#[derive(Debug, Clone)]
+pub struct Printf(String, Vec<String>);
+
+impl Command for Printf {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "printf",
+            aliases: &[],
+            args: "<format> [args...]",
+            summary: "Output formatted arguments",
+            flags: &[],
+        }
+    }
+
+    /// Parses `printf format [args...]`.
+    fn parse(args: &[&str]) -> Option<Self> {
+        let mut words: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        if words.is_empty() {
+            return None;
+        }
+        let format = words.remove(0);
+        Some(Printf(format, words))
+    }
+
+    fn pipe_input(&self) -> PipeInput {
+        PipeInput::Unexpected
+    }
+
+    fn execute(&self, _: &mut Context) -> Result<CommandOutput, ShellError> {
+        printf(&self.0, &self.1)
+    }
+}
+
+/// Write pipe input to files, appending if `append`, and pass it on
+/// to the next command unchanged. Only takes input from pipe.
+/// Tee(files, append)
+#[derive(Debug, Clone)]
+pub struct Tee(Vec<PathBuf>, bool);
+
+impl Command for Tee {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "tee",
+            aliases: &[],
+            args: "[-a] <files...>",
+            summary: "Write pipe input to files and pass it on",
+            flags: &[("-a", "Append to the files")],
+        }
+    }
+
+    /// Parses `tee [-a] files...`.
+    fn parse(args: &[&str]) -> Option<Self> {
+        let (append, files) = match args {
+            ["-a", files @ ..] => (true, files),
+            files => (false, files),
+        };
+        if files.is_empty() || files.iter().any(|f| f.starts_with('-')) {
+            return None;
+        }
+        Some(Tee(files.iter().map(PathBuf::from).collect(), append))
+    }
+
+    fn pipe_input(&self) -> PipeInput {
+        PipeInput::Required
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        let input = context.input(self)?;
+        tee(input, &context.resolve(&self.0), self.1)
+    }
+}
+
+/// Change how command output is printed from now on.
+#[derive(Debug, Clone)]
+pub struct SetOutputFormat(OutputFormat);
+
+impl Command for SetOutputFormat {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "output-format",
+            aliases: &[],
+            args: "text|json|csv",
+            summary: "Change how output is printed",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        match args {
+            [name] => OutputFormat::from_name(name).map(SetOutputFormat),
+            _ => None,
+        }
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        context.session.settings.output_format = self.0;
+        Ok(CommandOutput::None)
+    }
+}
+
+/// Parses the `on` or `off` argument of the commands switching modes.
+fn parse_on_off(args: &[&str]) -> Option<bool> {
+    match args {
+        ["on"] => Some(true),
+        ["off"] => Some(false),
+        _ => None,
+    }
+}
+
+/// Turn dry-run mode on or off. In dry-run mode commands changing
+/// files list what they would do instead of doing it.
+#[derive(Debug, Clone)]
+pub struct SetDryRun(bool);
+
+impl Command for SetDryRun {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "dry-run",
+            aliases: &[],
+            args: "on|off",
+            summary: "List changes to files instead of making them",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        parse_on_off(args).map(SetDryRun)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        context.session.settings.change_mode.dry_run = self.0;
+        Ok(CommandOutput::None)
+    }
+}
+
+/// Turn trash mode on or off. In trash mode `rm` moves files to the
+/// trash instead of deleting them.
+#[derive(Debug, Clone)]
+pub struct SetTrash(bool);
+
+impl Command for SetTrash {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "trash",
+            aliases: &[],
+            args: "on|off",
+            summary: "Move removed files to the trash",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        parse_on_off(args).map(SetTrash)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        context.session.settings.change_mode.trash = self.0;
+        Ok(CommandOutput::None)
+    }
+}
+
+/// Put a file back from the trash, given its name in the trash or its
+/// original path. Lists the trash if None.
+#[derive(Debug, Clone)]
+pub struct Restore(Option<String>);
+
+impl Command for Restore {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "restore",
+            aliases: &[],
+            args: "[name|path]",
+            summary: "Put a file back from the trash, or list the trash",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        match args {
+            [] => Some(Restore(None)),
+            [name] => Some(Restore(Some(name.to_string()))),
+            _ => None,
+        }
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        let session = &context.session;
+        restore(
+            self.0.as_deref(),
+            session.cwd(),
+            &session.settings.change_mode,
+        )
+    }
+}
+
+/// List the commands, or show how to use one.
+#[derive(Debug, Clone)]
+pub struct Help(Option<String>);
+
+impl Command for Help {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "help",
+            aliases: &[],
+            args: "[command]",
+            summary: "List the built-in commands, or show how to use one",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        match args {
+            [] => Some(Help(None)),
+            [name] => Some(Help(Some(name.to_string()))),
+            _ => None,
+        }
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        help(context.session.registry(), self.0.as_deref())
+    }
+}
+
+/// Parses the names `type` and `which` look up, at least one.
+fn parse_names(args: &[&str]) -> Option<Vec<String>> {
+    if args.is_empty() {
+        return None;
+    }
+    Some(args.iter().map(|name| name.to_string()).collect())
+}
+
+/// Tell whether each name is a built-in, an alias or a program.
+#[derive(Debug, Clone)]
+pub struct Type(Vec<String>);
+
+impl Command for Type {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "type",
+            aliases: &[],
+            args: "<names...>",
+            summary: "Tell whether names are built-ins, aliases or programs",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        parse_names(args).map(Type)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        type_of(context.session.registry(), &self.0)
+    }
+}
+
+/// Show what runs for each name.
+#[derive(Debug, Clone)]
+pub struct Which(Vec<String>);
+
+impl Command for Which {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "which",
+            aliases: &[],
+            args: "<names...>",
+            summary: "Show what runs for each name",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        parse_names(args).map(Which)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        which(context.session.registry(), &self.0)
+    }
+}
+
+/// List the jobs started with `&`.
+#[derive(Debug, Clone)]
+pub struct Jobs;
+
+impl Command for Jobs {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "jobs",
+            aliases: &[],
+            args: "",
+            summary: "List background jobs",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        match args {
+            [] => Some(Jobs),
+            _ => None,
+        }
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        let columns = vec![
+            "job".to_string(),
+            "state".to_string(),
+            "command".to_string(),
+        ];
+        let rows = context
+            .session
+            .jobs()
+            .list()
+            .iter()
+            .map(|job| {
+                let state = job.state().name().to_string();
+                vec![format!("%{}", job.id), state, job.line.clone()]
+            })
+            .collect();
+        Ok(CommandOutput::Table(columns, rows))
+    }
+}
+
+/// Parses the arguments of the commands taking an optional job, `[%n]`.
+/// The inner None is for the latest job.
+fn parse_job(args: &[&str]) -> Option<Option<usize>> {
+    match args {
+        [] => Some(None),
+        [job] => parse_job_id(job).map(Some),
+        _ => None,
+    }
+}
+
+/// Bring a job to the foreground: wait for it and show its output.
+/// Ctrl-C kills it and Ctrl-Z stops it.
+#[derive(Debug, Clone)]
+pub struct Fg(Option<usize>);
+
+impl Command for Fg {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "fg",
+            aliases: &[],
+            args: "[%job]",
+            summary: "Wait for a background job",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        parse_job(args).map(Fg)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        let job = context.session.jobs_mut().take(self.0)?;
+        job.resume();
+        match job.wait() {
+            Ok((result, _)) => result,
+            Err(job) => {
+                let stopped = format!("[{}] Stopped {}", job.id, job.line);
+                context.session.jobs_mut().insert(job);
+                Ok(CommandOutput::Single(stopped))
+            }
+        }
+    }
+}
+
+/// Let a job stopped by Ctrl-Z carry on in the background.
+#[derive(Debug, Clone)]
+pub struct Bg(Option<usize>);
+
+impl Command for Bg {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "bg",
+            aliases: &[],
+            args: "[%job]",
+            summary: "Resume a stopped job in the background",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        parse_job(args).map(Bg)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        let job = context.session.jobs().get(self.0)?;
+        job.resume();
+        Ok(CommandOutput::Single(format!(
+            "[{}] {} &",
+            job.id, job.line
+        )))
+    }
+}
+
+/// Wait for a job, or every running job, to be done. Their output is
+/// shown with the other finished jobs.
+#[derive(Debug, Clone)]
+pub struct Wait(Option<usize>);
+
+impl Command for Wait {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "wait",
+            aliases: &[],
+            args: "[%job]",
+            summary: "Wait for background jobs to be done",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        parse_job(args).map(Wait)
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        context.session.jobs().wait(self.0)?;
+        Ok(CommandOutput::None)
+    }
+}
+
+/// Stop a job. It ends at its next checkpoint, see `CancellationToken`,
+/// and is then reported like other finished jobs.
+#[derive(Debug, Clone)]
+pub struct Kill(usize);
+
+impl Command for Kill {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "kill",
+            aliases: &[],
+            args: "<%job>",
+            summary: "Stop a background job",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        match args {
+            [job] => parse_job_id(job).map(Kill),
+            _ => None,
+        }
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        context.session.jobs().get(Some(self.0))?.kill();
+        Ok(CommandOutput::None)
+    }
+}
+
+/// End the session, with the given status or the one of the last command.
+#[derive(Debug, Clone)]
+pub struct Exit(Option<i32>);
+
+impl Command for Exit {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "exit",
+            aliases: &[],
+            args: "[status]",
+            summary: "Exit the shell",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        match args {
+            [] => Some(Exit(None)),
+            [status] => status.parse().ok().map(|status| Exit(Some(status))),
+            _ => None,
+        }
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        let status = self.0.unwrap_or_else(|| context.session.status());
+        context.session.exit(status);
+        Ok(CommandOutput::None)
+    }
+}
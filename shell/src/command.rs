@@ -0,0 +1,376 @@
+//! The interface every command of the shell implements, and the registry
+//! finding commands by name. Built-ins are registered like any other
+//! command, so other crates can add their own next to them.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::error::ShellError;
+use crate::session::Session;
+use crate::shell_command::{split_quoted, ShellCommand};
+use crate::CommandOutput;
+
+/// Everything a user needs to know to call a command.
+#[derive(Debug)]
+pub struct CommandInfo {
+    pub name: &'static str,
+    /// Other names the command can be called by.
+    pub aliases: &'static [&'static str],
+    /// Arguments of the command, e.g. `[-n count] [files...]`.
+    pub args: &'static str,
+    /// One line description of what the command does.
+    pub summary: &'static str,
+    /// Flags the command accepts with what they do.
+    pub flags: &'static [(&'static str, &'static str)],
+}
+
+impl CommandInfo {
+    /// Usage line, e.g. `head [-n count] [files...]`.
+    pub fn usage(&self) -> String {
+        if self.args.is_empty() {
+            self.name.to_string()
+        } else {
+            format!("{} {}", self.name, self.args)
+        }
+    }
+}
+
+/// How a command, given its arguments, takes the output of the previous
+/// command of a pipe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PipeInput {
+    /// The command never reads pipe input.
+    NotAccepted,
+    /// The command can read pipe input, but not with these arguments,
+    /// e.g. `head` given files.
+    Unexpected,
+    /// The command only works on pipe input.
+    Required,
+}
+
+/// How the output of a command is best shown on a terminal, when printed
+/// as text.
+pub enum Presentation {
+    /// Printed as it is.
+    Plain,
+    /// Shown in the pager, with a title.
+    Paged(String),
+    /// Each line printed through a function adding colors.
+    Colored(fn(&str) -> String),
+}
+
+/// A command of the shell. The registry parses a command line into one,
+/// which then runs with its pipe input in a `Context`.
+/// Implementors also need `Clone`, see `CloneCommand`.
+pub trait Command: fmt::Debug + CloneCommand + Send + Sync {
+    /// Name, usage and description of the command.
+    fn info() -> &'static CommandInfo
+    where
+        Self: Sized;
+
+    /// Parses the arguments following the command name. Returns None if
+    /// they are wrong, the user is then shown the usage line.
+    fn parse(args: &[&str]) -> Option<Self>
+    where
+        Self: Sized;
+
+    /// How this command takes pipe input. The shell checks it before
+    /// running the command, so `execute` only gets input when it can use
+    /// it.
+    fn pipe_input(&self) -> PipeInput {
+        PipeInput::NotAccepted
+    }
+
+    /// Whether `--lossy` may be passed to decode invalid UTF-8 in text
+    /// pipe input instead of failing.
+    fn accepts_lossy(&self) -> bool {
+        false
+    }
+
+    /// Runs the command.
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError>;
+
+    /// Status of this command given its `result`, for scripts to check:
+    /// 0 for success and the status of the error otherwise, see
+    /// `ShellError::status`.
+    fn status(&self, result: &Result<CommandOutput, ShellError>) -> i32 {
+        match result {
+            Ok(_) => 0,
+            Err(e) => e.status(),
+        }
+    }
+
+    /// How the output of this command is shown on a terminal.
+    fn presentation(&self) -> Presentation {
+        Presentation::Plain
+    }
+}
+
+/// Clones commands behind a `Box<dyn Command>`. Implemented for every
+/// command that is `Clone`.
+pub trait CloneCommand {
+    fn clone_box(&self) -> Box<dyn Command>;
+}
+
+impl<C: Command + Clone + 'static> CloneCommand for C {
+    fn clone_box(&self) -> Box<dyn Command> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Command> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// What a command runs with.
+pub struct Context<'a> {
+    /// Output of the previous command of the pipe, if any.
+    pub input: Option<CommandOutput>,
+    /// Whether invalid UTF-8 in text input is replaced instead of failing.
+    pub lossy: bool,
+    /// The session running the command: its commands, current directory,
+    /// variables and settings.
+    pub session: &'a mut Session,
+}
+
+impl Context<'_> {
+    /// `paths` relative to the current directory of the session, see
+    /// `Session::resolve`.
+    pub fn resolve(&self, paths: &[PathBuf]) -> Vec<PathBuf> {
+        paths
+            .iter()
+            .map(|path| self.session.resolve(path))
+            .collect()
+    }
+
+    /// Takes the pipe input of `command` as it is.
+    pub fn input(&mut self, command: &dyn Command) -> Result<CommandOutput, ShellError> {
+        self.input
+            .take()
+            .ok_or_else(|| ShellError::ExpectedPipeInput(ShellCommand::from(command)))
+    }
+
+    /// Takes the pipe input of `command` as text, lists and tables as one
+    /// line per entry, see `CommandOutput::piped`. Raw bytes must be valid
+    /// UTF-8 unless lossy.
+    pub fn text_input(&mut self, command: &dyn Command) -> Result<String, ShellError> {
+        match self.input(command)?.piped() {
+            output @ CommandOutput::Bytes(_) if !self.lossy => output
+                .to_string()
+                .ok_or_else(|| ShellError::InvalidUtf8(ShellCommand::from(command))),
+            output => output
+                .to_lossy_string()
+                .ok_or_else(|| ShellError::NoInputForPipe(ShellCommand::from(command))),
+        }
+    }
+
+    /// Takes the pipe input of `command` for commands working on bytes.
+    /// Binary data is kept as it is, lists and tables are text as for
+    /// `text_input`.
+    pub fn bytes_input(&mut self, command: &dyn Command) -> Result<Vec<u8>, ShellError> {
+        match self.input(command)?.piped() {
+            CommandOutput::Bytes(bytes) => Ok(bytes),
+            output => output
+                .to_string()
+                .map(String::into_bytes)
+                .ok_or_else(|| ShellError::NoInputForPipe(ShellCommand::from(command))),
+        }
+    }
+}
+
+/// A registered command: its description and how to parse it.
+#[derive(Clone)]
+struct Entry {
+    info: &'static CommandInfo,
+    parse: fn(&[&str]) -> Option<Box<dyn Command>>,
+}
+
+/// The commands the shell knows, by name.
+#[derive(Clone, Default)]
+pub struct Registry {
+    entries: Vec<Entry>,
+}
+
+impl Registry {
+    /// An empty registry.
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    /// A registry with every built-in command.
+    pub fn with_builtins() -> Self {
+        let mut registry = Registry::new();
+        crate::builtins::register_all(&mut registry);
+        registry
+    }
+
+    /// Adds the command `C`. It replaces any command of the same name, so
+    /// built-ins can be overridden.
+    pub fn register<C: Command + 'static>(&mut self) {
+        let info = C::info();
+        let parse = |args: &[&str]| C::parse(args).map(|c| Box::new(c) as Box<dyn Command>);
+        match self.entries.iter_mut().find(|e| e.info.name == info.name) {
+            Some(entry) => *entry = Entry { info, parse },
+            None => self.entries.push(Entry { info, parse }),
+        }
+    }
+
+    /// Every command, in the order they were registered.
+    pub fn commands(&self) -> impl Iterator<Item = &'static CommandInfo> + '_ {
+        self.entries.iter().map(|entry| entry.info)
+    }
+
+    fn entry(&self, name: &str) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .find(|e| e.info.name == name || e.info.aliases.contains(&name))
+    }
+
+    /// The command called `name`, by its name or one of its aliases.
+    pub fn find(&self, name: &str) -> Option<&'static CommandInfo> {
+        self.entry(name).map(|entry| entry.info)
+    }
+
+    /// Parses a command without pipes: a name followed by arguments
+    /// separated by spaces, see `split_quoted`. Commands get their
+    /// arguments without the quotes.
+    pub fn parse(&self, command: &str) -> Result<Box<dyn Command>, ShellError> {
+        let words = split_quoted(command).ok_or_else(|| self.wrong_args(command))?;
+        let parts: Vec<&str> = words.iter().map(String::as_str).collect();
+        let name = parts.first().copied().unwrap_or_default();
+        let entry = self.entry(name).ok_or_else(|| self.unknown(name))?;
+        (entry.parse)(&parts[1..]).ok_or_else(|| self.wrong_args(command))
+    }
+
+    /// Error for a `command` given the wrong arguments, with the usage line
+    /// of the command.
+    pub fn wrong_args(&self, command: &str) -> ShellError {
+        let name = command.split(' ').next().unwrap_or_default();
+        let usage = self.find(name).map(CommandInfo::usage).unwrap_or_default();
+        ShellError::KnownCommandWrongArgs(command.to_string(), usage, None)
+    }
+
+    /// Error for an unknown command `name`, suggesting the closest known
+    /// command.
+    pub fn unknown(&self, name: &str) -> ShellError {
+        let suggestion = self.suggest(name).map(str::to_string);
+        ShellError::UnknownCommand(name.to_string(), suggestion, None)
+    }
+
+    /// The name or alias closest to `name` by edit distance, if close
+    /// enough to be a typo: at most 2 edits, less than a third of `name`
+    /// for short names.
+    pub fn suggest(&self, name: &str) -> Option<&'static str> {
+        let max = (name.chars().count() / 3).clamp(1, 2);
+        self.entries
+            .iter()
+            .flat_map(|entry| std::iter::once(&entry.info.name).chain(entry.info.aliases))
+            .map(|candidate| (edit_distance(name, candidate), *candidate))
+            .filter(|&(distance, _)| distance <= max)
+            .min_by_key(|&(distance, _)| distance)
+            .map(|(_, candidate)| candidate)
+    }
+}
+
+/// Edit distance between `a` and `b`: the number of characters to insert,
+/// remove or replace, or pairs of neighbours to swap, to turn one into the
+/// other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // distances[i][j] is the distance between the first i characters of a
+    // and the first j of b.
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in 0..=a.len() {
+        for j in 0..=b.len() {
+            distances[i][j] = match (i, j) {
+                (0, j) => j,
+                (i, 0) => i,
+                (i, j) => {
+                    let replace = distances[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+                    let mut distance = replace
+                        .min(distances[i - 1][j] + 1)
+                        .min(distances[i][j - 1] + 1);
+                    if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                        distance = distance.min(distances[i - 2][j - 2] + 1);
+                    }
+                    distance
+                }
+            };
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+/// Commands registered next to the built-ins are parsed, run and listed
+/// like them.
+#[test]
+fn register_test() {
+    #[derive(Debug, Clone)]
+    struct Shout;
+
+    impl Command for Shout {
+        fn info() -> &'static CommandInfo {
+            &CommandInfo {
+                name: "shout",
+                aliases: &["yell"],
+                args: "",
+                summary: "Uppercase pipe input",
+                flags: &[],
+            }
+        }
+
+        fn parse(args: &[&str]) -> Option<Self> {
+            match args {
+                [] => Some(Shout),
+                _ => None,
+            }
+        }
+
+        fn pipe_input(&self) -> PipeInput {
+            PipeInput::Required
+        }
+
+        fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+            Ok(CommandOutput::Single(
+                context.text_input(self)?.to_uppercase(),
+            ))
+        }
+    }
+
+    let mut session = Session::new();
+    session.registry_mut().register::<Shout>();
+    let mut run = |input| session.run(input);
+    assert_eq!(
+        run("echo -n hi | yell").unwrap().to_string(),
+        Some("HI".to_string())
+    );
+    assert!(matches!(
+        run("shout"),
+        Err(ShellError::ExpectedPipeInput(_))
+    ));
+    assert!(matches!(
+        run("shout loudly"),
+        Err(ShellError::KnownCommandWrongArgs(..))
+    ));
+    let registry = session.registry();
+    assert_eq!(registry.commands().last().unwrap().name, "shout");
+}
+
+/// Unknown commands suggest the closest name or alias, if close enough.
+#[test]
+fn suggest_test() {
+    let registry = Registry::with_builtins();
+    assert_eq!(registry.suggest("fnd-string"), Some("find-string"));
+    assert_eq!(registry.suggest("hed"), Some("head"));
+    assert_eq!(registry.suggest("cta"), Some("cat"));
+    assert_eq!(registry.suggest("xyzzy"), None);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("cta", "cat"), 1);
+    assert_eq!(
+        registry.unknown("fnd-string").to_string(),
+        "unknown command: fnd-string, did you mean find-string?"
+    );
+}
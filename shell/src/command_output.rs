@@ -1,3 +1,7 @@
+use std::io;
+use std::io::prelude::*;
+
+use crate::parser::{tokens, TokenKind};
 
 /// Represents the output of a ShellCommand.
 /// Some commands output a single line, others multiple lines,
@@ -6,28 +10,332 @@
 pub enum CommandOutput {
     Single(String),
     List(Vec<String>),
+    /// Structured output made of named columns and one row of values
+    /// per entry. Every row has exactly one value per column.
+    /// Table(columns, rows)
+    Table(Vec<String>, Vec<Vec<String>>),
+    /// Raw data that is not valid UTF-8, e.g. the contents of a binary file.
+    Bytes(Vec<u8>),
     None,
 }
 
+/// The different ways a CommandOutput can be printed.
+/// `Text` is meant for humans, `Json` and `Csv` for other programs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    /// One JSON object per line (JSON lines).
+    Json,
+    /// A header line with the field names followed by one line per record.
+    Csv,
+}
+
+impl OutputFormat {
+    /// Parses a format name: `text`, `json` or `csv`.
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name {
+            "text" => Some(OutputFormat::Text),
+            "json" => Some(OutputFormat::Json),
+            "csv" => Some(OutputFormat::Csv),
+            _ => None,
+        }
+    }
+
+    /// Parses a format flag: `--text`, `--json` or `--csv`.
+    pub fn from_flag(flag: &str) -> Option<OutputFormat> {
+        flag.strip_prefix("--").and_then(OutputFormat::from_name)
+    }
+
+    /// Removes the format flag ending the command line `cli_input`, if its
+    /// last word is one. Flags anywhere else, or in quotes, are arguments.
+    /// Returns the remaining command line and the format flag, if any.
+    /// e.g. `ls | find-string rs --json` -> (`ls | find-string rs`, Some(Json))
+    pub fn take_flag(cli_input: &str) -> (String, Option<OutputFormat>) {
+        let last = tokens(cli_input).pop();
+        if let Some((TokenKind::Word, span)) = last {
+            if let Some(format) = OutputFormat::from_flag(&cli_input[span.start..span.end]) {
+                let rest = cli_input[..span.start].trim_end();
+                return (rest.to_string(), Some(format));
+            }
+        }
+        (cli_input.to_string(), None)
+    }
+}
+
 impl CommandOutput {
-    /// Prints different variants for CommandOutput.
-    /// `Single` prints the sole line with a newline.
+    /// Wraps file contents: `Single` if they are valid UTF-8,
+    /// `Bytes` otherwise.
+    pub fn from_bytes(bytes: Vec<u8>) -> CommandOutput {
+        match String::from_utf8(bytes) {
+            Ok(s) => CommandOutput::Single(s),
+            Err(e) => CommandOutput::Bytes(e.into_bytes()),
+        }
+    }
+
+    /// Prints different variants for CommandOutput using `format`.
+    /// `Single` prints its text, ending with a single newline.
     /// `List` prints one entry per line.
+    /// `Table` prints one row per line.
+    /// `Bytes` are written to stdout unchanged, in text format.
     /// `None` prints nothing.
-    pub fn print_command(&self) {
-        // Step #3
-        // Match on the difference variants of CommandOutput!
-        match self {
-            _ => { unimplemented!() }
+    pub fn print_as(&self, format: OutputFormat) {
+        if let (CommandOutput::Bytes(bytes), OutputFormat::Text) = (self, format) {
+            let mut stdout = io::stdout();
+            stdout.write_all(bytes).expect("Could not write to stdout");
+            stdout.flush().expect("Could not flush stdout");
+            return;
+        }
+        for line in self.render(format) {
+            println!("{}", line);
+        }
+    }
+
+    /// Lines of text making up this output, as it would be printed in
+    /// text mode.
+    pub fn lines(&self) -> Vec<String> {
+        self.render(OutputFormat::Text)
+            .iter()
+            .flat_map(|s| s.lines())
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    /// Renders this CommandOutput as the lines `print_as` would print.
+    ///
+    /// Field names are stable: `Single` and `List` entries are records
+    /// with a single `value` field, `Table` rows use the table's columns.
+    /// `Bytes` are rendered as text, replacing invalid UTF-8 sequences.
+    pub fn render(&self, format: OutputFormat) -> Vec<String> {
+        let (columns, rows) = match self {
+            CommandOutput::None => return vec![],
+            // Text usually ends its last line already.
+            CommandOutput::Single(s) if format == OutputFormat::Text => {
+                return vec![s.strip_suffix('\n').unwrap_or(s).to_string()];
+            }
+            CommandOutput::List(ss) if format == OutputFormat::Text => return ss.clone(),
+            CommandOutput::Table(_, rows) if format == OutputFormat::Text => {
+                return rows.iter().map(|row| row.join("\t")).collect();
+            }
+            CommandOutput::Bytes(bytes) if format == OutputFormat::Text => {
+                return vec![String::from_utf8_lossy(bytes).into_owned()];
+            }
+            CommandOutput::Single(s) => (value_column(), vec![vec![s.clone()]]),
+            CommandOutput::Bytes(bytes) => {
+                let s = String::from_utf8_lossy(bytes).into_owned();
+                (value_column(), vec![vec![s]])
+            }
+            CommandOutput::List(ss) => {
+                (value_column(), ss.iter().map(|s| vec![s.clone()]).collect())
+            }
+            CommandOutput::Table(columns, rows) => (columns.clone(), rows.clone()),
+        };
+
+        match format {
+            OutputFormat::Json => rows.iter().map(|row| json_object(&columns, row)).collect(),
+            _ => {
+                let mut lines = vec![csv_line(&columns)];
+                lines.extend(rows.iter().map(|row| csv_line(row)));
+                lines
+            }
         }
     }
 
     /// Converts variants into a single String.
     /// `None` returns None.
     /// List concats the strings separated by a single space.
+    /// Table puts one row per line, with values separated by tabs.
+    /// Bytes that are not valid UTF-8 return None, see `to_lossy_string`.
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_string(self) -> Option<String> {
-        // Step #4
-        // Match on the difference variants of CommandOutput
-        unimplemented!()
+        match self {
+            CommandOutput::Bytes(bytes) => String::from_utf8(bytes).ok(),
+            CommandOutput::List(results) => Some(results.join(" ")),
+            CommandOutput::Single(result) => Some(result),
+            CommandOutput::Table(_, rows) => Some(
+                rows.iter()
+                    .map(|row| row.join("\t"))
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+            ),
+            CommandOutput::None => None,
+        }
+    }
+
+    /// Like `to_string` but invalid UTF-8 in `Bytes` is replaced by U+FFFD
+    /// instead of failing.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_lossy_string(self) -> Option<String> {
+        match self {
+            CommandOutput::Bytes(bytes) => Some(String::from_utf8_lossy(&bytes).into_owned()),
+            output => output.to_string(),
+        }
+    }
+
+    /// This output the way the next command of a pipe reads it. Lists and
+    /// tables become text with one line per entry or row, each ending with
+    /// a newline, as they would be printed. Other outputs are kept.
+    pub fn piped(self) -> CommandOutput {
+        match self {
+            output @ (CommandOutput::List(_) | CommandOutput::Table(..)) => {
+                CommandOutput::Single(output.lines().iter().map(|l| format!("{}\n", l)).collect())
+            }
+            output => output,
+        }
+    }
+
+    /// This output followed by `other`, e.g. for the commands of a script.
+    /// Text and bytes are written one after the other like on a terminal,
+    /// lists and tables with the same columns keep their entries. Anything
+    /// else becomes a list of their lines.
+    pub fn append(self, other: CommandOutput) -> CommandOutput {
+        match (self, other) {
+            (CommandOutput::None, output) | (output, CommandOutput::None) => output,
+            (CommandOutput::Single(mut a), CommandOutput::Single(b)) => {
+                a.push_str(&b);
+                CommandOutput::Single(a)
+            }
+            (
+                a @ (CommandOutput::Single(_) | CommandOutput::Bytes(_)),
+                b @ (CommandOutput::Single(_) | CommandOutput::Bytes(_)),
+            ) => {
+                let mut bytes = a.into_bytes();
+                bytes.extend(b.into_bytes());
+                CommandOutput::from_bytes(bytes)
+            }
+            (CommandOutput::List(mut a), CommandOutput::List(b)) => {
+                a.extend(b);
+                CommandOutput::List(a)
+            }
+            (CommandOutput::Table(columns, mut rows), CommandOutput::Table(other, more))
+                if columns == other =>
+            {
+                rows.extend(more);
+                CommandOutput::Table(columns, rows)
+            }
+            (a, b) => {
+                let mut lines = a.lines();
+                lines.extend(b.lines());
+                CommandOutput::List(lines)
+            }
+        }
+    }
+
+    /// Text or bytes as bytes, for `append`.
+    fn into_bytes(self) -> Vec<u8> {
+        match self {
+            CommandOutput::Bytes(bytes) => bytes,
+            output => output.to_string().unwrap_or_default().into_bytes(),
+        }
+    }
+}
+
+fn value_column() -> Vec<String> {
+    vec!["value".to_string()]
+}
+
+/// Builds a single line JSON object out of matching `keys` and `values`.
+pub fn json_object(keys: &[String], values: &[String]) -> String {
+    let fields: Vec<String> = keys
+        .iter()
+        .zip(values)
+        .map(|(k, v)| format!("{}:{}", json_string(k), json_string(v)))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+/// Quotes and escapes `s` as a JSON string.
+pub fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Joins `fields` into a CSV line, quoting fields when needed.
+fn csv_line(fields: &[String]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|f| {
+            if f.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", f.replace('"', "\"\""))
+            } else {
+                f.clone()
+            }
+        })
+        .collect();
+    fields.join(",")
+}
+
+/// Lists and tables are piped as one line per entry, ending with a newline.
+#[test]
+fn piped_test() {
+    let output = CommandOutput::List(vec!["a.txt".to_string(), "b.txt".to_string()]);
+    assert_eq!(
+        output.piped().to_string(),
+        Some("a.txt\nb.txt\n".to_string())
+    );
+    let output = CommandOutput::Table(
+        vec!["count".to_string(), "line".to_string()],
+        vec![vec!["2".to_string(), "x".to_string()]],
+    );
+    assert_eq!(output.piped().to_string(), Some("2\tx\n".to_string()));
+    assert_eq!(
+        CommandOutput::List(vec![]).piped().to_string(),
+        Some(String::new())
+    );
+}
+
+/// Lists are one JSON object per entry, all using the `value` field.
+#[test]
+fn render_list_json_test() {
+    let output = CommandOutput::List(vec!["a.txt".to_string(), "say \"hi\"\n".to_string()]);
+    assert_eq!(
+        output.render(OutputFormat::Json),
+        vec![r#"{"value":"a.txt"}"#, r#"{"value":"say \"hi\"\n"}"#]
+    );
+}
+
+/// Tables print a header then one line per row, quoting where CSV requires it.
+#[test]
+fn render_table_csv_test() {
+    let output = CommandOutput::Table(
+        vec!["name".to_string(), "size".to_string()],
+        vec![
+            vec!["a,b".to_string(), "10".to_string()],
+            vec!["c".to_string(), "2".to_string()],
+        ],
+    );
+    assert_eq!(
+        output.render(OutputFormat::Csv),
+        vec!["name,size", "\"a,b\",10", "c,2"]
+    );
+    assert_eq!(output.render(OutputFormat::Text), vec!["a,b\t10", "c\t2"]);
+}
+
+/// Only a format flag ending the command line is removed from it.
+#[test]
+fn take_flag_test() {
+    assert_eq!(
+        OutputFormat::take_flag("ls | find-string rs --json "),
+        ("ls | find-string rs".to_string(), Some(OutputFormat::Json))
+    );
+    assert_eq!(OutputFormat::take_flag("ls"), ("ls".to_string(), None));
+    for line in [
+        "ls --json | find-string rs",
+        "echo --csv x",
+        "echo '--json'",
+        "echo \"a --csv\"",
+    ] {
+        assert_eq!(OutputFormat::take_flag(line), (line.to_string(), None));
     }
 }
@@ -1,69 +1,510 @@
 //! This module implements the functions that do the actual IO for each
 //! of our commands.
 
+use crate::checksum::HashAlgorithm;
+use crate::error::ShellError;
+use crate::jobs::CancellationToken;
+use crate::CommandOutput;
+use std::cmp::Ordering;
+use std::ffi::CStr;
+use std::fmt;
 use std::fs::File;
+use std::fs::{DirEntry, Metadata};
 use std::io;
 use std::io::prelude::*;
-use std::io::ErrorKind;
-use std::path::Path;
+use std::io::{BufReader, ErrorKind, SeekFrom};
+use std::iter::Peekable;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
 use std::path::PathBuf;
-use std::fs::DirEntry;
-// Step #1 Check out src/error.rs
-use crate::error::ShellError;
-// Step #2 Check out src/command_output.
-use crate::CommandOutput;
+use std::path::{Component, Path};
+use std::str::Chars;
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 /// "grep"-like function. Returns all the lines in `content` where `search_str`
-/// is a substring.
-pub fn find_string<'a>(content: &String, search_str: &String) -> CommandOutput {
-    // Step #5 (see command_output.rs for #3 and #4)
-    // Now that you're familar with CommandOutput you are ready
-    // to implement this function.
-
-    // Have a look at the str::split() function in the standar library documentation
-    // (std docs)
-    // Also look at str::contains()
+/// is a substring. Stops with `Interrupted` once `token` is cancelled.
+pub fn find_string(
+    content: &str,
+    search_str: &str,
+    token: &CancellationToken,
+) -> Result<CommandOutput, ShellError> {
     let mut matches: Vec<String> = vec![];
-    unimplemented!();
-    CommandOutput::List(matches)
+
+    for line in content.split('\n') {
+        token.checkpoint()?;
+        if line_matches(line, search_str) {
+            matches.push(line.to_string());
+        }
+    }
+    Ok(CommandOutput::List(matches))
+}
+
+/// Whether `line` is matched by `search_str` when searching with find-string.
+pub fn line_matches(line: &str, search_str: &str) -> bool {
+    line.contains(search_str)
 }
 
 /// Change all instances of `from` to `to` in string `original`
-pub fn subs_string(original: &String, from: &String, to: &String) -> CommandOutput {
-    // Step #6
-    // Check out str::replace() in std docs.
-    unimplemented!()
+pub fn subs_string(original: &str, from: &str, to: &str) -> CommandOutput {
+    CommandOutput::Single(original.replace(from, to))
 }
 
 /// Return contents of file `path` as one giant string.
 /// Returs string so that it can be reused by `cat_files` function.
+/// Fails with an `InvalidData` error naming the file if it is not UTF-8.
 pub fn more_file(path: impl AsRef<Path>) -> Result<String, ShellError> {
-    // Step #7
-    // See File::open().
-    // See std::fs::read_to_string()
-    // First try implementing using match expressions.
-    // then use '?' to get rid of all match expressions.
-    // Ask for help if needed! This can be tricky the first time!
-    unimplemented!()
+    let contents = read_file(&path)?;
+    String::from_utf8(contents).map_err(|_| {
+        let err_msg = format!("{} is not valid UTF-8", path.as_ref().display());
+        ShellError::IoError(io::Error::new(ErrorKind::InvalidData, err_msg))
+    })
+}
+
+/// Return the raw contents of file `path`, whatever the encoding.
+pub fn read_file(path: impl AsRef<Path>) -> Result<Vec<u8>, ShellError> {
+    let mut f = File::open(path)?;
+    let mut contents = vec![];
+    f.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// Returns contents of file `path` for display.
+/// Text files are returned as is. Binary files would garble the terminal
+/// so they are returned as a hexdump instead.
+pub fn more_or_hexdump(path: impl AsRef<Path>) -> Result<CommandOutput, ShellError> {
+    match more_file(&path) {
+        Ok(contents) if !is_binary(contents.as_bytes()) => Ok(CommandOutput::Single(contents)),
+        Ok(contents) => Ok(CommandOutput::List(hexdump(contents.as_bytes(), 0))),
+        // Not UTF-8 so definitely not text.
+        Err(ShellError::IoError(ref e)) if e.kind() == ErrorKind::InvalidData => {
+            Ok(CommandOutput::List(hexdump(&read_file(path)?, 0)))
+        }
+        Err(e) => Err(e),
+    }
 }
 
 /// Returns conents of all files in `paths` as a CommandOutput.
 /// concats files based on their order in slice.
-/// Fails if any of the files have any IO error.
-pub fn cat_files(paths: &[PathBuf]) -> Result<CommandOutput, ShellError> {
-    // Step #8
-    // See string::push_str()
-    unimplemented!()
+/// Files are concatenated byte for byte, if the result is not valid UTF-8
+/// it is returned as `CommandOutput::Bytes`.
+/// Fails if any of the files have any IO error. Files are read in chunks so
+/// big ones stop with `Interrupted` soon after `token` is cancelled.
+pub fn cat_files(
+    paths: &[PathBuf],
+    token: &CancellationToken,
+) -> Result<CommandOutput, ShellError> {
+    let mut cat = vec![];
+    let mut chunk = vec![0; 64 * 1024];
+    for p in paths {
+        let mut f = File::open(p).map_err(path_error(p))?;
+        loop {
+            token.checkpoint()?;
+            match f.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => cat.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+    Ok(CommandOutput::from_bytes(cat))
+}
+
+/// Guess whether `contents` is binary data rather than text.
+/// Like most tools we look for NUL bytes near the start of the data,
+/// anything that is not valid UTF-8 also counts as binary.
+pub fn is_binary(contents: &[u8]) -> bool {
+    let start = &contents[..contents.len().min(8000)];
+    start.contains(&0) || std::str::from_utf8(contents).is_err()
+}
+
+/// Formats `contents` as lines of offset, hex bytes and printable ASCII,
+/// 16 bytes per line, e.g.
+/// `00000000  6f 6d 61 72 0a                                    |omar.|`
+/// Offsets start at `offset`, where `contents` were taken from.
+pub fn hexdump(contents: &[u8], offset: usize) -> Vec<String> {
+    contents
+        .chunks(16)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!(
+                "{:08x}  {:<47}  |{}|",
+                offset + i * 16,
+                hex.join(" "),
+                ascii
+            )
+        })
+        .collect()
+}
+
+/// Formats `contents` like xxd: offset, hex bytes in groups of two and
+/// printable ASCII, 16 bytes per line, e.g.
+/// `00000000: 6f6d 6172 0a                             omar.`
+/// Offsets start at `offset`, where `contents` were taken from.
+pub fn xxd(contents: &[u8], offset: usize) -> Vec<String> {
+    contents
+        .chunks(16)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex: Vec<String> = chunk
+                .chunks(2)
+                .map(|pair| pair.iter().map(|b| format!("{:02x}", b)).collect())
+                .collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:08x}: {:<39}  {}", offset + i * 16, hex.join(" "), ascii)
+        })
+        .collect()
+}
+
+/// Options of `hexdump` and `xxd`.
+#[derive(Clone, Debug, Default)]
+pub struct HexdumpOptions {
+    /// Number of bytes to leave out at the start.
+    pub skip: usize,
+    /// Maximum number of bytes to show.
+    pub length: Option<usize>,
+    /// Turn a hexdump back into bytes instead.
+    pub reverse: bool,
+    /// Lay the dump out like xxd instead of `hexdump -C`.
+    pub xxd: bool,
+}
+
+/// "hexdump -C"-like function. Shows `contents`, or the part of it selected
+/// by `options`, as a hexdump, or like xxd does with `options.xxd`. In
+/// reverse mode `contents` must be a dump in either layout and the bytes it
+/// shows are returned.
+pub fn hexdump_bytes(
+    contents: &[u8],
+    options: &HexdumpOptions,
+) -> Result<CommandOutput, ShellError> {
+    if options.reverse {
+        let text =
+            std::str::from_utf8(contents).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        return Ok(CommandOutput::from_bytes(unhexdump(text)?));
+    }
+
+    let start = options.skip.min(contents.len());
+    let end = match options.length {
+        Some(length) => contents.len().min(start.saturating_add(length)),
+        None => contents.len(),
+    };
+    let lines = if options.xxd {
+        xxd(&contents[start..end], start)
+    } else {
+        hexdump(&contents[start..end], start)
+    };
+    Ok(CommandOutput::Single(lines.join("\n")))
+}
+
+/// Hexdump of the contents of `paths` one after the other, see
+/// `hexdump_bytes`.
+pub fn hexdump_files(
+    paths: &[PathBuf],
+    options: &HexdumpOptions,
+) -> Result<CommandOutput, ShellError> {
+    let mut contents = vec![];
+    for p in paths {
+        contents.append(&mut read_file(p)?);
+    }
+    hexdump_bytes(&contents, options)
+}
+
+/// Reads back the bytes shown by `hexdump` or `xxd`, told apart by the `:`
+/// xxd puts after offsets. Bytes go at the offset of their line, gaps
+/// between lines are filled with zeros.
+/// A `*` line, for rows left out because they repeat the one before, is
+/// that row repeated up to the offset of the next line. A line with only
+/// an offset, as `hexdump -C` ends with, gives the total length.
+/// The ASCII column is ignored, so it can be left out.
+pub fn unhexdump(text: &str) -> Result<Vec<u8>, ShellError> {
+    let mut bytes = vec![];
+    // The bytes of the last row, and whether a `*` repeats them.
+    let mut row = vec![];
+    let mut repeat = false;
+    for (i, line) in text.lines().enumerate() {
+        match line.trim() {
+            "" => continue,
+            "*" => {
+                repeat = true;
+                continue;
+            }
+            _ => {}
+        }
+        let invalid = || {
+            let err_msg = format!("Invalid hexdump line {}: {}", i + 1, line);
+            ShellError::IoError(io::Error::new(ErrorKind::InvalidData, err_msg))
+        };
+        let (offset, rest) = line
+            .trim_start()
+            .split_once(' ')
+            .unwrap_or((line.trim(), ""));
+        let hex: String = match offset.strip_suffix(':') {
+            // xxd: groups of hex digits, then two spaces before the ASCII.
+            Some(_) => {
+                let rest = rest.trim_start();
+                rest.split_once("  ").map_or(rest, |(hex, _)| hex)
+            }
+            None => rest.split_once('|').map_or(rest, |(hex, _)| hex),
+        }
+        .split_whitespace()
+        .collect();
+        let offset =
+            usize::from_str_radix(offset.trim_end_matches(':'), 16).map_err(|_| invalid())?;
+
+        if repeat && !row.is_empty() {
+            while bytes.len() < offset {
+                let n = row.len().min(offset - bytes.len());
+                bytes.extend_from_slice(&row[..n]);
+            }
+        }
+        repeat = false;
+        if bytes.len() < offset {
+            bytes.resize(offset, 0);
+        }
+        bytes.truncate(offset);
+        for i in (0..hex.len()).step_by(2) {
+            let byte = hex.get(i..i + 2).ok_or_else(invalid)?;
+            bytes.push(u8::from_str_radix(byte, 16).map_err(|_| invalid())?);
+        }
+        row = bytes[offset..].to_vec();
+    }
+    Ok(bytes)
+}
+
+/// "stat"-like function. Returns a table with one row of metadata per
+/// path: type, size, permissions, owner, group, inode, link count and
+/// access, modification and status change times in UTC.
+/// Symlinks are described themselves, not what they point to.
+pub fn stat(paths: &[PathBuf]) -> Result<CommandOutput, ShellError> {
+    let columns = [
+        "file", "type", "size", "mode", "owner", "group", "inode", "links", "accessed", "modified",
+        "changed",
+    ];
+    let mut rows = vec![];
+    for path in paths {
+        let metadata = std::fs::symlink_metadata(path).map_err(path_error(path))?;
+        rows.push(vec![
+            path.display().to_string(),
+            file_kind(&metadata).to_string(),
+            metadata.len().to_string(),
+            format!("{:04o}", metadata.mode() & 0o7777),
+            user_name(metadata.uid()),
+            group_name(metadata.gid()),
+            metadata.ino().to_string(),
+            metadata.nlink().to_string(),
+            format_time(metadata.atime()),
+            format_time(metadata.mtime()),
+            format_time(metadata.ctime()),
+        ]);
+    }
+    let columns = columns.iter().map(|c| c.to_string()).collect();
+    Ok(CommandOutput::Table(columns, rows))
+}
+
+fn file_kind(metadata: &Metadata) -> &'static str {
+    let file_type = metadata.file_type();
+    if file_type.is_symlink() {
+        "symbolic link"
+    } else if file_type.is_dir() {
+        "directory"
+    } else if file_type.is_file() {
+        "regular file"
+    } else if file_type.is_fifo() {
+        "fifo"
+    } else if file_type.is_socket() {
+        "socket"
+    } else if file_type.is_block_device() {
+        "block device"
+    } else {
+        "character device"
+    }
+}
+
+/// Name of the user `uid`, or the number itself if it has none.
+fn user_name(uid: u32) -> String {
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut found = std::ptr::null_mut();
+    let error = unsafe {
+        libc::getpwuid_r(
+            uid,
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut found,
+        )
+    };
+    if error != 0 || found.is_null() {
+        return uid.to_string();
+    }
+    unsafe { CStr::from_ptr(passwd.pw_name) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Name of the group `gid`, or the number itself if it has none.
+fn group_name(gid: u32) -> String {
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    let mut group: libc::group = unsafe { std::mem::zeroed() };
+    let mut found = std::ptr::null_mut();
+    let error = unsafe {
+        libc::getgrgid_r(
+            gid,
+            &mut group,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut found,
+        )
+    };
+    if error != 0 || found.is_null() {
+        return gid.to_string();
+    }
+    unsafe { CStr::from_ptr(group.gr_name) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Formats seconds since the Unix epoch as `YYYY-MM-DD HH:MM:SS` in UTC.
+fn format_time(secs: i64) -> String {
+    let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    // Civil date from days since 1970-01-01, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// "file"-like function. Guesses what kind of data each path holds from
+/// the magic bytes at its start, falling back to telling text from binary
+/// data. Returns a table with columns `file` and `type`.
+pub fn file_types(paths: &[PathBuf]) -> Result<CommandOutput, ShellError> {
+    let mut rows = vec![];
+    for path in paths {
+        let metadata = std::fs::symlink_metadata(path).map_err(path_error(path))?;
+        let kind = if metadata.is_file() {
+            let mut start = vec![];
+            File::open(path)
+                .and_then(|f| f.take(8192).read_to_end(&mut start))
+                .map_err(path_error(path))?;
+            sniff(&start).to_string()
+        } else {
+            file_kind(&metadata).to_string()
+        };
+        rows.push(vec![path.display().to_string(), kind]);
+    }
+    Ok(CommandOutput::Table(
+        vec!["file".to_string(), "type".to_string()],
+        rows,
+    ))
+}
+
+/// Kind of data `start`, the first bytes of a file, belongs to.
+fn sniff(start: &[u8]) -> &'static str {
+    let magic: [(&[u8], &str); 11] = [
+        (b"\x7fELF", "ELF executable"),
+        (b"\x89PNG\r\n\x1a\n", "PNG image"),
+        (b"\xff\xd8\xff", "JPEG image"),
+        (b"GIF87a", "GIF image"),
+        (b"GIF89a", "GIF image"),
+        (b"%PDF-", "PDF document"),
+        (b"\x1f\x8b", "gzip compressed data"),
+        (b"BZh", "bzip2 compressed data"),
+        (b"\xfd7zXZ\0", "XZ compressed data"),
+        (b"PK\x03\x04", "Zip archive"),
+        (b"#!", "script text"),
+    ];
+    if let Some((_, kind)) = magic.iter().find(|(bytes, _)| start.starts_with(bytes)) {
+        return kind;
+    }
+
+    // The last character may have been cut in half when reading.
+    let text = match std::str::from_utf8(start) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    if start.is_empty() {
+        "empty"
+    } else if start.contains(&0) || !text {
+        "data"
+    } else if start.is_ascii() {
+        "ASCII text"
+    } else {
+        "UTF-8 text"
+    }
+}
+
+/// "sha256sum"/"md5sum"-like function. Returns a table with the hash of
+/// each path, in columns `hash` and `file`.
+pub fn checksum_files(
+    paths: &[PathBuf],
+    algorithm: HashAlgorithm,
+) -> Result<CommandOutput, ShellError> {
+    let mut rows = vec![];
+    for path in paths {
+        let contents = std::fs::read(path).map_err(path_error(path))?;
+        rows.push(vec![
+            algorithm.hex_digest(&contents),
+            path.display().to_string(),
+        ]);
+    }
+    Ok(CommandOutput::Table(checksum_columns(), rows))
+}
+
+/// Hash of `contents`, the output of the previous command in a pipe. Its
+/// file is called `-`.
+pub fn checksum(contents: &[u8], algorithm: HashAlgorithm) -> CommandOutput {
+    let row = vec![algorithm.hex_digest(contents), "-".to_string()];
+    CommandOutput::Table(checksum_columns(), vec![row])
+}
+
+fn checksum_columns() -> Vec<String> {
+    vec!["hash".to_string(), "file".to_string()]
 }
 
 /// Returns all entries in a directory.
 /// Fails if `path` is not a directory.
 pub fn ls_dir(path: &impl AsRef<Path>) -> Result<CommandOutput, ShellError> {
-    // Step #9
-    // Use std::fs::read_dir()
-    // as well as the get_path function defined below to turn a DirEntry
-    // into a String.
-    unimplemented!()
+    let mut entries = vec![];
+    for dir_entry in std::fs::read_dir(path)? {
+        entries.push(get_path(dir_entry?)?);
+    }
+    Ok(CommandOutput::List(entries))
 }
 
 fn get_path(dir: DirEntry) -> Result<String, ShellError> {
@@ -75,33 +516,1651 @@ fn get_path(dir: DirEntry) -> Result<String, ShellError> {
         .map_err(|_| io::Error::new(ErrorKind::InvalidData, err_msg))?)
 }
 
-/// This one was hard to implement. Even for me. So this should probably
-/// be one of those bonus 3-star excercises.
+// This one was hard to implement. Even for me. So this should probably
+// be one of those bonus 3-star excercises.
+
+/// An entry met while walking a directory with `walk_dir`.
+struct WalkEntry {
+    path: PathBuf,
+    /// Metadata of the entry itself, symlinks are not followed.
+    metadata: Metadata,
+    /// Whether this entry, and each of its parents under the starting
+    /// directory, is the last one of its directory. Outermost parent first.
+    last: Vec<bool>,
+}
+
+impl WalkEntry {
+    /// How deep the entry is, 1 for entries of the starting directory.
+    fn depth(&self) -> usize {
+        self.last.len()
+    }
+}
+
+/// Recursively goes through all dirs and subdirs in `dir`, calling `visit`
+/// on every entry before the entries under it. Entries of a directory are
+/// visited in name order. Does not go deeper than `max_depth` if set.
+/// Stops with `Interrupted` once `token` is cancelled.
+fn walk_dir<F>(
+    dir: &Path,
+    max_depth: Option<usize>,
+    token: &CancellationToken,
+    visit: &mut F,
+) -> Result<(), ShellError>
+where
+    F: FnMut(&WalkEntry),
+{
+    fn walk_dir_rec<F>(
+        current_dir: &Path,
+        max_depth: Option<usize>,
+        last: &mut Vec<bool>,
+        token: &CancellationToken,
+        visit: &mut F,
+    ) -> Result<(), ShellError>
+    where
+        F: FnMut(&WalkEntry),
+    {
+        let mut entries = std::fs::read_dir(current_dir)?.collect::<Result<Vec<DirEntry>, _>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        let count = entries.len();
+        for (i, entry) in entries.into_iter().enumerate() {
+            token.checkpoint()?;
+            last.push(i + 1 == count);
+            let entry = WalkEntry {
+                path: entry.path(),
+                metadata: entry.metadata()?,
+                last: last.clone(),
+            };
+            visit(&entry);
+
+            // Recurse!
+            if entry.metadata.is_dir() && max_depth.is_none_or(|max| entry.depth() < max) {
+                walk_dir_rec(&entry.path, max_depth, last, token, visit)?;
+            }
+            last.pop();
+        }
+        Ok(())
+    }
+
+    walk_dir_rec(dir, max_depth, &mut vec![], token, visit)
+}
 
 /// Find all instances of `search_name` by recusively going through all
-/// dirs and subdirs in `starting_dir`.
+/// dirs and subdirs in `starting_dir`, until `token` is cancelled.
 pub fn find_file<P: AsRef<Path>, Q: AsRef<Path>>(
     starting_dir: P,
     search_name: Q,
+    token: &CancellationToken,
 ) -> Result<CommandOutput, ShellError> {
-    // Bonus command. Very difficult to implement! Leave for last!
+    let mut entries = vec![];
+    walk_dir(starting_dir.as_ref(), None, token, &mut |entry| {
+        if entry.path.file_name() == Some(search_name.as_ref().as_os_str()) {
+            entries.push(entry.path.clone());
+        }
+    })?;
+    let entries: Vec<String> = entries
+        .into_iter()
+        .map(|path| {
+            path.into_os_string()
+                .into_string()
+                .expect("Path is invalid UTF-8")
+        })
+        .collect();
+    Ok(CommandOutput::List(entries))
+}
 
-    // Feel free to look at the solution implementation and then
-    // try to implement it yourself.
-    unimplemented!()
+/// Draws the hierarchy under `path` with box-drawing characters, going at
+/// most `max_depth` levels deep if set.
+/// Returns a table with a single `tree` column, one row per line.
+/// Stops with `Interrupted` once `token` is cancelled.
+pub fn tree(
+    path: &Path,
+    max_depth: Option<usize>,
+    token: &CancellationToken,
+) -> Result<CommandOutput, ShellError> {
+    if !path.is_dir() {
+        return Err(ShellError::PathError(
+            path.to_path_buf(),
+            io::Error::new(ErrorKind::InvalidInput, "Not a directory"),
+        ));
+    }
+
+    let mut rows = vec![vec![path.display().to_string()]];
+    walk_dir(path, max_depth, token, &mut |entry| {
+        let (last, parents_last) = entry.last.split_last().expect("Entries have a depth");
+        let mut line: String = parents_last
+            .iter()
+            .map(|&parent_last| if parent_last { "    " } else { "│   " })
+            .collect();
+        line.push_str(if *last { "└── " } else { "├── " });
+        line.push_str(&entry.path.file_name().unwrap_or_default().to_string_lossy());
+        rows.push(vec![line]);
+    })?;
+    Ok(CommandOutput::Table(vec!["tree".to_string()], rows))
 }
 
-/// Change current working directory.
-pub fn cd(path: &impl AsRef<Path>) -> Result<CommandOutput, ShellError> {
-    // Step #10
-    // See std::env::set_current_dir
-    unimplemented!()
+/// Options of `du`.
+#[derive(Clone, Debug, Default)]
+pub struct DuOptions {
+    /// Only print the total of the starting directory.
+    pub summarize: bool,
+    /// Print sizes with a unit, e.g. `1.5K`, instead of in bytes.
+    pub human: bool,
 }
 
-// Step #11
-// These are sample tests. Feel free to write your own to gain confidence
-// in your implementation!
+/// Sums the sizes of the files in `path` and every directory under it.
+/// Returns a table with columns `size` and `path`, where a directory comes
+/// after the directories inside it. Stops with `Interrupted` once `token` is
+/// cancelled.
+pub fn du(
+    path: &Path,
+    options: &DuOptions,
+    token: &CancellationToken,
+) -> Result<CommandOutput, ShellError> {
+    let metadata = std::fs::symlink_metadata(path).map_err(path_error(path))?;
+    // Directories in the order they were met, with their depth.
+    let mut dirs = vec![(path.to_path_buf(), 0)];
+    let mut sizes = vec![0];
+    let mut total = metadata.len();
+    if metadata.is_dir() {
+        total = 0;
+        // Indices in `dirs` of the parents of the entry being visited.
+        let mut parents = vec![0];
+        walk_dir(path, None, token, &mut |entry| {
+            parents.truncate(entry.depth());
+            if entry.metadata.is_dir() {
+                parents.push(dirs.len());
+                dirs.push((entry.path.clone(), entry.depth()));
+                sizes.push(0);
+            } else {
+                for &parent in &parents {
+                    sizes[parent] += entry.metadata.len();
+                }
+                total += entry.metadata.len();
+            }
+        })?;
+    }
+    sizes[0] = total;
 
+    let size = |bytes: u64| {
+        if options.human {
+            human_size(bytes)
+        } else {
+            bytes.to_string()
+        }
+    };
+    let row = |i: usize| vec![size(sizes[i]), dirs[i].0.display().to_string()];
+    let columns = vec!["size".to_string(), "path".to_string()];
+    if options.summarize {
+        return Ok(CommandOutput::Table(columns, vec![row(0)]));
+    }
+
+    // A directory is printed once we are done with everything inside it.
+    let mut rows = vec![];
+    let mut pending: Vec<usize> = vec![];
+    for (i, (_, depth)) in dirs.iter().enumerate() {
+        while pending.last().is_some_and(|&p| dirs[p].1 >= *depth) {
+            rows.push(row(pending.pop().unwrap()));
+        }
+        pending.push(i);
+    }
+    rows.extend(pending.into_iter().rev().map(row));
+    Ok(CommandOutput::Table(columns, rows))
+}
+
+/// Formats a number of bytes using the largest unit it has at least one
+/// of, e.g. `512`, `1.5K`, `20M`.
+fn human_size(bytes: u64) -> String {
+    let units = ["K", "M", "G", "T", "P"];
+    let mut size = bytes as f64;
+    let mut unit = "";
+    for u in units {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = u;
+    }
+    if unit.is_empty() {
+        bytes.to_string()
+    } else if size < 10.0 {
+        format!("{:.1}{}", size, unit)
+    } else {
+        format!("{:.0}{}", size, unit)
+    }
+}
+
+/// "cd"-like function. Checks `path` is a directory and returns it to be
+/// the new current directory, with `.` and `..` taken out where possible.
+pub fn cd(path: &Path) -> Result<PathBuf, ShellError> {
+    let metadata = std::fs::metadata(path).map_err(path_error(path))?;
+    if !metadata.is_dir() {
+        let e = io::Error::from(ErrorKind::NotADirectory);
+        return Err(ShellError::PathError(path.to_path_buf(), e));
+    }
+
+    let mut dir = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(dir.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                dir.pop();
+            }
+            component => dir.push(component),
+        }
+    }
+    Ok(dir)
+}
+
+/// Replaces the escape sequences `echo -e` and `printf` understand:
+/// `\n`, `\t`, `\r`, `\a`, `\b`, `\e`, `\f`, `\v`, `\\`, `\0nnn` in octal
+/// and `\xHH` in hex. Returns the text and whether a `\c` asked to stop
+/// there.
+pub fn expand_escapes(s: &str) -> (String, bool) {
+    let mut expanded = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => expanded.push('\n'),
+            Some('t') => expanded.push('\t'),
+            Some('r') => expanded.push('\r'),
+            Some('a') => expanded.push('\x07'),
+            Some('b') => expanded.push('\x08'),
+            Some('e') => expanded.push('\x1b'),
+            Some('f') => expanded.push('\x0c'),
+            Some('v') => expanded.push('\x0b'),
+            Some('\\') => expanded.push('\\'),
+            Some('c') => return (expanded, true),
+            Some('0') => expanded.push(char::from(take_digits(&mut chars, 8, 3).0 as u8)),
+            Some('x') => match take_digits(&mut chars, 16, 2) {
+                (_, 0) => expanded.push_str("\\x"),
+                (value, _) => expanded.push(char::from(value as u8)),
+            },
+            Some(other) => {
+                expanded.push('\\');
+                expanded.push(other);
+            }
+            None => expanded.push('\\'),
+        }
+    }
+    (expanded, false)
+}
+
+/// Reads up to `max` digits in `radix` from `chars`.
+/// Returns their value and how many there were.
+fn take_digits(chars: &mut Peekable<Chars>, radix: u32, max: usize) -> (u32, usize) {
+    let mut value: u32 = 0;
+    let mut digits = 0;
+    while digits < max {
+        match chars.peek().and_then(|d| d.to_digit(radix)) {
+            Some(digit) => value = value.saturating_mul(radix).saturating_add(digit),
+            None => break,
+        }
+        digits += 1;
+        chars.next();
+    }
+    (value, digits)
+}
+
+/// "printf"-like function. Fills the conversions in `format` with `args`:
+/// `%s`, `%d`/`%i`, `%f`, `%x`, `%o`, `%c` and `%%`, with optional `-` or
+/// `0` flags, a width and a precision, e.g. `%-8s` or `%.2f`.
+/// Escape sequences are replaced as with `echo -e`. The format is used
+/// again while there are arguments left, missing ones are empty or 0.
+pub fn printf(format: &str, args: &[String]) -> Result<CommandOutput, ShellError> {
+    let (format, _) = expand_escapes(format);
+    let mut output = String::new();
+    let mut args = args.iter();
+    loop {
+        let mut used_args = false;
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                output.push(c);
+                continue;
+            }
+            if chars.peek() == Some(&'%') {
+                chars.next();
+                output.push('%');
+                continue;
+            }
+
+            let mut flags = String::new();
+            while let Some(&flag @ ('-' | '0' | '+' | ' ')) = chars.peek() {
+                flags.push(flag);
+                chars.next();
+            }
+            let width = take_digits(&mut chars, 10, usize::MAX);
+            let precision = if chars.peek() == Some(&'.') {
+                chars.next();
+                Some(take_digits(&mut chars, 10, usize::MAX).0 as usize)
+            } else {
+                None
+            };
+            let conversion = chars
+                .next()
+                .ok_or_else(|| printf_error("Unfinished conversion"))?;
+
+            used_args = true;
+            let arg = args.next().map(String::as_str);
+            let number = |arg: Option<&str>| -> Result<f64, ShellError> {
+                match arg {
+                    None => Ok(0.0),
+                    Some(arg) => arg
+                        .parse()
+                        .map_err(|_| printf_error(&format!("Invalid number: {}", arg))),
+                }
+            };
+            let sign = |n: f64| {
+                if flags.contains('+') && n >= 0.0 {
+                    "+"
+                } else {
+                    ""
+                }
+            };
+            let value = match conversion {
+                's' => {
+                    let s = arg.unwrap_or("");
+                    match precision {
+                        Some(precision) => s.chars().take(precision).collect(),
+                        None => s.to_string(),
+                    }
+                }
+                'c' => arg
+                    .and_then(|a| a.chars().next())
+                    .map(String::from)
+                    .unwrap_or_default(),
+                'd' | 'i' => {
+                    let n = number(arg)?.trunc();
+                    format!("{}{}", sign(n), n as i64)
+                }
+                'f' => {
+                    let n = number(arg)?;
+                    format!("{}{:.*}", sign(n), precision.unwrap_or(6), n)
+                }
+                'x' => format!("{:x}", number(arg)? as i64),
+                'X' => format!("{:X}", number(arg)? as i64),
+                'o' => format!("{:o}", number(arg)? as i64),
+                c => return Err(printf_error(&format!("Unknown conversion: %{}", c))),
+            };
+
+            let padding = (width.0 as usize).saturating_sub(value.chars().count());
+            if flags.contains('-') {
+                output.push_str(&value);
+                output.push_str(&" ".repeat(padding));
+            } else if flags.contains('0') && conversion != 's' && conversion != 'c' {
+                // Zeros go between the sign and the digits.
+                let digits = value.trim_start_matches(['-', '+']);
+                output.push_str(&value[..value.len() - digits.len()]);
+                output.push_str(&"0".repeat(padding));
+                output.push_str(digits);
+            } else {
+                output.push_str(&" ".repeat(padding));
+                output.push_str(&value);
+            }
+        }
+        if !used_args || args.len() == 0 {
+            break;
+        }
+    }
+    Ok(CommandOutput::Single(output))
+}
+
+fn printf_error(message: &str) -> ShellError {
+    ShellError::IoError(io::Error::new(ErrorKind::InvalidInput, message.to_string()))
+}
+
+/// Returns the first `n` lines of `content`.
+pub fn head_lines(content: &str, n: usize) -> CommandOutput {
+    let lines: Vec<&str> = content.lines().take(n).collect();
+    CommandOutput::Single(lines.join("\n"))
+}
+
+/// Returns the last `n` lines of `content`.
+pub fn tail_lines(content: &str, n: usize) -> CommandOutput {
+    let lines: Vec<&str> = content.lines().collect();
+    let start = lines.len().saturating_sub(n);
+    CommandOutput::Single(lines[start..].join("\n"))
+}
+
+/// Returns the first `n` lines of every file in `paths`.
+/// Only reads as much of each file as needed. Invalid UTF-8 is replaced,
+/// as in `tail_files`.
+pub fn head_files(paths: &[PathBuf], n: usize) -> Result<CommandOutput, ShellError> {
+    lines_of_files(paths, |path| {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut contents = vec![];
+        for _ in 0..n {
+            if reader.read_until(b'\n', &mut contents)? == 0 {
+                break;
+            }
+        }
+
+        let contents = String::from_utf8_lossy(&contents);
+        Ok(contents.strip_suffix('\n').unwrap_or(&contents).to_string())
+    })
+}
+
+/// Returns the last `n` lines of every file in `paths`.
+/// Files are read backwards from the end so large files are not loaded
+/// entirely.
+pub fn tail_files(paths: &[PathBuf], n: usize) -> Result<CommandOutput, ShellError> {
+    lines_of_files(paths, |path| {
+        let mut f = File::open(path)?;
+        let start = tail_offset(&mut f, n)?;
+        let mut contents = vec![];
+        f.seek(SeekFrom::Start(start))?;
+        f.read_to_end(&mut contents)?;
+
+        let contents = String::from_utf8_lossy(&contents);
+        Ok(contents.strip_suffix('\n').unwrap_or(&contents).to_string())
+    })
+}
+
+/// Runs `f` on every path, putting a `==> path <==` header before each
+/// result when there is more than one file.
+fn lines_of_files<F>(paths: &[PathBuf], f: F) -> Result<CommandOutput, ShellError>
+where
+    F: Fn(&PathBuf) -> Result<String, ShellError>,
+{
+    if let [path] = paths {
+        return Ok(CommandOutput::Single(f(path)?));
+    }
+
+    let mut sections = vec![];
+    for path in paths {
+        sections.push(format!("==> {} <==\n{}", path.display(), f(path)?));
+    }
+    Ok(CommandOutput::Single(sections.join("\n\n")))
+}
+
+/// Byte offset in `f` where its last `n` lines start.
+fn tail_offset(f: &mut File, n: usize) -> Result<u64, ShellError> {
+    const BLOCK_SIZE: u64 = 4096;
+
+    let len = f.metadata()?.len();
+    let mut block = vec![0; BLOCK_SIZE as usize];
+    let mut end = len;
+    let mut newlines = 0;
+
+    if n == 0 {
+        return Ok(len);
+    }
+    while end > 0 {
+        let start = end.saturating_sub(BLOCK_SIZE);
+        let block = &mut block[..(end - start) as usize];
+        f.seek(SeekFrom::Start(start))?;
+        f.read_exact(block)?;
+
+        for (i, byte) in block.iter().enumerate().rev() {
+            let offset = start + i as u64;
+            // A newline at the very end closes the last line, it does not
+            // start a new one.
+            if *byte == b'\n' && offset + 1 != len {
+                newlines += 1;
+                if newlines == n {
+                    return Ok(offset + 1);
+                }
+            }
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+/// "tail -f": hands the last `n` lines of every file in `paths` to `print`,
+/// then every line appended to them, until an IO error or `token` is
+/// cancelled.
+/// Lines are handed over once complete, as a list, so `print` can show them
+/// in any format.
+pub fn follow_files(
+    paths: &[PathBuf],
+    n: usize,
+    print: impl Fn(CommandOutput),
+    token: &CancellationToken,
+) -> Result<CommandOutput, ShellError> {
+    let mut positions = vec![];
+    for path in paths {
+        positions.push(File::open(path)?.metadata()?.len());
+    }
+    print(tail_files(paths, n)?);
+
+    // The end of the last line of each file, until it is complete.
+    let mut partial = vec![vec![]; paths.len()];
+    let mut last_printed = paths.len() - 1;
+
+    loop {
+        token.checkpoint()?;
+        for (i, path) in paths.iter().enumerate() {
+            let mut f = File::open(path)?;
+            let len = f.metadata()?.len();
+            if len < positions[i] {
+                // Truncated, start over from the beginning.
+                positions[i] = 0;
+                partial[i].clear();
+            }
+            if len == positions[i] {
+                continue;
+            }
+
+            let appended = &mut partial[i];
+            let start = appended.len();
+            f.seek(SeekFrom::Start(positions[i]))?;
+            f.read_to_end(appended)?;
+            positions[i] += (appended.len() - start) as u64;
+            let complete = match appended.iter().rposition(|&b| b == b'\n') {
+                Some(end) => appended.drain(..=end).collect::<Vec<u8>>(),
+                None => continue,
+            };
+
+            let mut lines = vec![];
+            if paths.len() > 1 && last_printed != i {
+                lines.push(String::new());
+                lines.push(format!("==> {} <==", path.display()));
+                last_printed = i;
+            }
+            let complete = String::from_utf8_lossy(&complete[..complete.len() - 1]);
+            lines.extend(complete.split('\n').map(str::to_string));
+            print(CommandOutput::List(lines));
+        }
+        thread::sleep(Duration::from_millis(250));
+    }
+}
+
+/// Which counts `wc` reports. All of them if none is selected.
+#[derive(Clone, Debug, Default)]
+pub struct WcOptions {
+    pub lines: bool,
+    pub words: bool,
+    pub bytes: bool,
+    pub chars: bool,
+}
+
+/// How `sort` orders lines.
+#[derive(Clone, Debug, Default)]
+pub struct SortOptions {
+    /// Compare by the leading number of the key instead of as text.
+    pub numeric: bool,
+    pub reverse: bool,
+    /// Only compare the whitespace separated field at this position,
+    /// starting from 1.
+    pub key: Option<usize>,
+    /// Only keep the first of lines with equal keys.
+    pub unique: bool,
+}
+
+/// Returns the concatenated contents of all files in `paths` as text.
+pub fn cat_to_string(paths: &[PathBuf]) -> Result<String, ShellError> {
+    let mut cat = String::new();
+    for p in paths {
+        cat.push_str(&more_file(p)?);
+    }
+    Ok(cat)
+}
+
+/// "wc"-like function. Counts lines, words, bytes and characters in
+/// `content`, as a table with one column per selected count.
+pub fn wc(content: &str, options: &WcOptions) -> CommandOutput {
+    let (columns, counts) = wc_counts(content, options);
+    CommandOutput::Table(columns, vec![counts])
+}
+
+/// Counts for every file in `paths`, one row per file with an extra `file`
+/// column, plus a `total` row when there are several files.
+pub fn wc_files(paths: &[PathBuf], options: &WcOptions) -> Result<CommandOutput, ShellError> {
+    let mut columns = vec![];
+    let mut rows = vec![];
+    let mut totals: Vec<usize> = vec![];
+
+    for path in paths {
+        let (names, counts) = wc_counts(&more_file(path)?, options);
+        totals.resize(counts.len(), 0);
+        for (total, count) in totals.iter_mut().zip(&counts) {
+            *total += count.parse::<usize>().unwrap_or(0);
+        }
+
+        columns = names;
+        let mut row = counts;
+        row.push(path.display().to_string());
+        rows.push(row);
+    }
+
+    if paths.len() > 1 {
+        let mut row: Vec<String> = totals.iter().map(|t| t.to_string()).collect();
+        row.push("total".to_string());
+        rows.push(row);
+    }
+    columns.push("file".to_string());
+    Ok(CommandOutput::Table(columns, rows))
+}
+
+fn wc_counts(content: &str, options: &WcOptions) -> (Vec<String>, Vec<String>) {
+    let all = !(options.lines || options.words || options.bytes || options.chars);
+    let counts = [
+        ("lines", options.lines, content.matches('\n').count()),
+        ("words", options.words, content.split_whitespace().count()),
+        ("bytes", options.bytes, content.len()),
+        ("chars", options.chars, content.chars().count()),
+    ];
+
+    counts
+        .iter()
+        .filter(|(_, selected, _)| all || *selected)
+        .map(|(name, _, count)| (name.to_string(), count.to_string()))
+        .unzip()
+}
+
+/// "sort"-like function. Sorts the lines of `content`.
+/// Lines with equal keys are ordered by the whole line.
+pub fn sort_lines(content: &str, options: &SortOptions) -> CommandOutput {
+    let key = |line: &str| -> String {
+        match options.key {
+            Some(k) => line.split_whitespace().nth(k - 1).unwrap_or("").to_string(),
+            None => line.to_string(),
+        }
+    };
+    let compare_keys = |a: &str, b: &str| {
+        if options.numeric {
+            leading_number(&key(a))
+                .partial_cmp(&leading_number(&key(b)))
+                .unwrap_or(Ordering::Equal)
+        } else {
+            key(a).cmp(&key(b))
+        }
+    };
+
+    let mut lines: Vec<&str> = content.lines().collect();
+    lines.sort_by(|a, b| {
+        let ordering = compare_keys(a, b).then_with(|| a.cmp(b));
+        if options.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+    if options.unique {
+        lines.dedup_by(|a, b| compare_keys(a, b) == Ordering::Equal);
+    }
+    CommandOutput::Single(lines.join("\n"))
+}
+
+/// The number at the start of `s`, ignoring leading whitespace.
+/// 0 if there is none, like `sort -n`.
+fn leading_number(s: &str) -> f64 {
+    let s = s.trim_start();
+    let end = s
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || c == '.' || (i == 0 && c == '-')))
+        .map(|(i, _)| i)
+        .unwrap_or_else(|| s.len());
+    s[..end].parse().unwrap_or(0.0)
+}
+
+/// "uniq"-like function. Collapses runs of identical lines in `content`.
+/// With `count` returns a table of each line and how many times it was
+/// repeated.
+pub fn uniq_lines(content: &str, count: bool) -> CommandOutput {
+    let mut runs: Vec<(usize, &str)> = vec![];
+    for line in content.lines() {
+        match runs.last_mut() {
+            Some((n, last)) if *last == line => *n += 1,
+            _ => runs.push((1, line)),
+        }
+    }
+
+    if count {
+        let columns = vec!["count".to_string(), "line".to_string()];
+        let rows = runs
+            .iter()
+            .map(|(n, line)| vec![n.to_string(), line.to_string()])
+            .collect();
+        CommandOutput::Table(columns, rows)
+    } else {
+        let lines: Vec<&str> = runs.iter().map(|(_, line)| *line).collect();
+        CommandOutput::Single(lines.join("\n"))
+    }
+}
+
+/// "cut"-like function. Keeps the `fields` of every line of `content`,
+/// where fields are separated by `delimiter`. `fields` are inclusive
+/// ranges of field positions starting from 1.
+/// Lines without the delimiter are kept whole.
+pub fn cut_fields(content: &str, delimiter: char, fields: &[(usize, usize)]) -> CommandOutput {
+    let lines: Vec<String> = content
+        .lines()
+        .map(|line| {
+            if !line.contains(delimiter) {
+                return line.to_string();
+            }
+            let kept: Vec<&str> = line
+                .split(delimiter)
+                .enumerate()
+                .filter(|(i, _)| {
+                    fields
+                        .iter()
+                        .any(|(from, to)| from <= &(i + 1) && &(i + 1) <= to)
+                })
+                .map(|(_, field)| field)
+                .collect();
+            kept.join(&delimiter.to_string())
+        })
+        .collect();
+    CommandOutput::Single(lines.join("\n"))
+}
+
+/// "tr"-like function. Replaces every character of `content` found in
+/// `from` with the character at the same position in `to`, repeating the
+/// last character of `to` if it is shorter. If `to` is None the
+/// characters are deleted instead.
+/// Sets may contain ranges like `a-z`.
+pub fn translate(content: &str, from: &str, to: Option<&str>) -> CommandOutput {
+    let from = expand_set(from);
+    let to = to.map(expand_set);
+
+    let translated = content
+        .chars()
+        .filter_map(|c| match (from.iter().position(|&f| f == c), &to) {
+            (None, _) => Some(c),
+            (Some(_), None) => None,
+            (Some(i), Some(to)) => to.get(i).or_else(|| to.last()).copied(),
+        })
+        .collect();
+    CommandOutput::Single(translated)
+}
+
+/// Expands the ranges in a `tr` set, e.g. `a-d_` -> `abcd_`.
+fn expand_set(set: &str) -> Vec<char> {
+    let chars: Vec<char> = set.chars().collect();
+    let mut expanded = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        if i + 2 < chars.len() && chars[i + 1] == '-' && chars[i] <= chars[i + 2] {
+            expanded.extend(chars[i]..=chars[i + 2]);
+            i += 3;
+        } else {
+            expanded.push(chars[i]);
+            i += 1;
+        }
+    }
+    expanded
+}
+
+/// Compares files `old` and `new` line by line, see `diff`. A path of
+/// `-` stands for `input`, the output of the previous command in a pipe.
+/// Binary files are only reported as differing.
+pub fn diff_files(
+    old: &Path,
+    new: &Path,
+    input: Option<&str>,
+    context: usize,
+) -> Result<CommandOutput, ShellError> {
+    let read = |path: &Path| match input {
+        Some(input) if path == Path::new("-") => Ok(input.as_bytes().to_vec()),
+        _ => read_file(path).map_err(|e| match e {
+            ShellError::IoError(e) => ShellError::PathError(path.to_path_buf(), e),
+            e => e,
+        }),
+    };
+    let (old_contents, new_contents) = (read(old)?, read(new)?);
+    if is_binary(&old_contents) || is_binary(&new_contents) {
+        if old_contents == new_contents {
+            return Ok(CommandOutput::None);
+        }
+        return Ok(CommandOutput::Single(format!(
+            "Binary files {} and {} differ",
+            old.display(),
+            new.display()
+        )));
+    }
+
+    // Neither is binary so both are valid UTF-8.
+    let old_contents = String::from_utf8_lossy(&old_contents);
+    let new_contents = String::from_utf8_lossy(&new_contents);
+    let labels = (old.display().to_string(), new.display().to_string());
+    Ok(diff(&old_contents, &new_contents, &labels, context))
+}
+
+/// "diff -u"-like function. Returns the lines changed between `old` and
+/// `new` in unified format, with `context` unchanged lines around each
+/// change. `labels` name the old and new contents in the header.
+/// Returns `CommandOutput::None` if there are no differences.
+pub fn diff(old: &str, new: &str, labels: &(String, String), context: usize) -> CommandOutput {
+    // Lines keep their newline so a missing one at the end is a change.
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+    let edits = shortest_edit(&old, &new);
+    let changes: Vec<usize> = (0..edits.len()).filter(|&i| edits[i].0 != ' ').collect();
+    if changes.is_empty() {
+        return CommandOutput::None;
+    }
+
+    let mut lines = vec![format!("--- {}", labels.0), format!("+++ {}", labels.1)];
+    let mut i = 0;
+    while i < changes.len() {
+        // Changes less than two contexts apart share a hunk.
+        let mut j = i;
+        while j + 1 < changes.len() && changes[j + 1] - changes[j] <= 2 * context + 1 {
+            j += 1;
+        }
+        let start = changes[i].saturating_sub(context);
+        let end = (changes[j] + context + 1).min(edits.len());
+        let hunk = &edits[start..end];
+        let old_count = hunk.iter().filter(|edit| edit.0 != '+').count();
+        let new_count = hunk.iter().filter(|edit| edit.0 != '-').count();
+        lines.push(format!(
+            "@@ -{} +{} @@",
+            hunk_range(hunk[0].1, old_count),
+            hunk_range(hunk[0].2, new_count)
+        ));
+        for &(kind, old_line, new_line) in hunk {
+            let line = if kind == '+' {
+                new[new_line]
+            } else {
+                old[old_line]
+            };
+            lines.push(format!(
+                "{}{}",
+                kind,
+                line.strip_suffix('\n').unwrap_or(line)
+            ));
+            if !line.ends_with('\n') {
+                lines.push("\\ No newline at end of file".to_string());
+            }
+        }
+        i = j + 1;
+    }
+    CommandOutput::Single(lines.join("\n"))
+}
+
+/// Start and length of a hunk as printed in its header: lines are
+/// counted from 1, an empty hunk starts at the line before it.
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+/// Finds the fewest lines to delete from `old` and insert from `new` to
+/// turn one into the other, using Myers' algorithm.
+/// Returns every line as (kind, line in old, line in new) where kind is
+/// `' '` for unchanged lines, `'-'` for deleted and `'+'` for inserted ones.
+/// For insertions and deletions the other line is where the change happens.
+fn shortest_edit(old: &[&str], new: &[&str]) -> Vec<(char, usize, usize)> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+    let offset = max as usize;
+    // v[k] is the furthest x reached on diagonal k = x - y.
+    let mut v = vec![0isize; 2 * offset + 2];
+    let mut trace = vec![];
+
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let i = (k + max) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Walk back through the furthest points of each step.
+    let mut edits = vec![];
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let i = (k + max) as usize;
+        let previous_k = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = v[(previous_k + max) as usize];
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            edits.push((' ', x as usize, y as usize));
+        }
+        if d > 0 {
+            if x == previous_x {
+                edits.push(('+', x as usize, previous_y as usize));
+            } else {
+                edits.push(('-', previous_x as usize, y as usize));
+            }
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    edits.reverse();
+    edits
+}
+
+/// Colors a line of unified diff output for the terminal: deletions in
+/// red, insertions in green and hunk headers in cyan.
+pub fn color_diff_line(line: &str) -> String {
+    let color = if line.starts_with("---") || line.starts_with("+++") {
+        "1"
+    } else if line.starts_with("@@") {
+        "36"
+    } else if line.starts_with('-') {
+        "31"
+    } else if line.starts_with('+') {
+        "32"
+    } else {
+        return line.to_string();
+    };
+    format!("\x1b[{}m{}\x1b[0m", color, line)
+}
+
+/// Bytes `output` is written to a file as: text ends with a newline, lists
+/// and tables have one line per entry or row.
+pub(crate) fn file_contents(output: &CommandOutput) -> Vec<u8> {
+    match output {
+        CommandOutput::Bytes(bytes) => bytes.clone(),
+        CommandOutput::None => vec![],
+        output => (output.lines().join("\n") + "\n").into_bytes(),
+    }
+}
+
+/// "tee"-like function. Writes `input` to every file in `paths` as it
+/// would be printed, appending to them if `append`, and returns it
+/// unchanged for the next command of the pipe.
+/// Files that cannot be written fail with `ShellError::PartialFailure`,
+/// which still carries `input`.
+pub fn tee(
+    input: CommandOutput,
+    paths: &[PathBuf],
+    append: bool,
+) -> Result<CommandOutput, ShellError> {
+    let contents = file_contents(&input);
+
+    let mut errors = vec![];
+    for path in paths {
+        let written = File::options()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .and_then(|mut file| file.write_all(&contents));
+        if let Err(e) = written {
+            errors.push(ShellError::PathError(path.clone(), e));
+        }
+    }
+    match errors.len() {
+        0 => Ok(input),
+        1 => Err(ShellError::PartialFailure(
+            input,
+            Box::new(errors.remove(0)),
+        )),
+        _ => Err(ShellError::PartialFailure(
+            input,
+            Box::new(ShellError::MultipleErrors(errors)),
+        )),
+    }
+}
+
+/// Options of `rm`.
+#[derive(Clone, Debug, Default)]
+pub struct RmOptions {
+    /// Remove directories and their contents.
+    pub recursive: bool,
+    /// Ignore files that do not exist.
+    pub force: bool,
+    /// Allow removing the root or home directory recursively.
+    pub no_preserve_root: bool,
+}
+
+/// Expands the wildcards `*`, `?` and `[...]` in `patterns` into the
+/// matching paths, sorted. Patterns matching nothing are kept as is so the
+/// command using them can report the missing file.
+/// Hidden files are only matched by patterns starting with a dot.
+pub fn expand_globs(patterns: &[PathBuf]) -> Vec<PathBuf> {
+    let mut paths = vec![];
+    for pattern in patterns {
+        // Kept as given, globbing would drop a trailing `.`.
+        if !pattern.to_string_lossy().contains(['*', '?', '[']) {
+            paths.push(pattern.clone());
+            continue;
+        }
+        let mut matches = glob(pattern);
+        if matches.is_empty() {
+            paths.push(pattern.clone());
+        } else {
+            matches.sort();
+            paths.append(&mut matches);
+        }
+    }
+    paths
+}
+
+/// Paths matching `pattern`, walking one path component at a time.
+fn glob(pattern: &Path) -> Vec<PathBuf> {
+    let mut candidates = vec![PathBuf::new()];
+    for component in pattern.components() {
+        let component = component.as_os_str().to_string_lossy();
+        if !component.contains(['*', '?', '[']) {
+            for candidate in candidates.iter_mut() {
+                candidate.push(component.as_ref());
+            }
+            continue;
+        }
+
+        let mut matches = vec![];
+        for candidate in &candidates {
+            let dir = if candidate.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                candidate.as_path()
+            };
+            let entries = match std::fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let hidden = name.starts_with('.') && !component.starts_with('.');
+                if !hidden && glob_matches(&component, &name) {
+                    matches.push(candidate.join(name));
+                }
+            }
+        }
+        candidates = matches;
+    }
+    candidates.retain(|path| path.exists() || path.is_symlink());
+    candidates
+}
+
+/// Whether `name` matches the shell wildcard `pattern`.
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match pattern.first() {
+            None => name.is_empty(),
+            Some('*') => (0..=name.len()).any(|i| matches(&pattern[1..], &name[i..])),
+            Some('?') => !name.is_empty() && matches(&pattern[1..], &name[1..]),
+            Some('[') => match pattern.iter().position(|&c| c == ']') {
+                Some(end) if end > 1 && !name.is_empty() => {
+                    set_contains(&pattern[1..end], name[0])
+                        && matches(&pattern[end + 1..], &name[1..])
+                }
+                _ => name.first() == Some(&'[') && matches(&pattern[1..], &name[1..]),
+            },
+            Some(&c) => name.first() == Some(&c) && matches(&pattern[1..], &name[1..]),
+        }
+    }
+
+    /// `[abc]`, `[a-z]` and negated `[!a-z]` character sets.
+    fn set_contains(set: &[char], c: char) -> bool {
+        let (negated, set) = match set.first() {
+            Some('!') | Some('^') => (true, &set[1..]),
+            _ => (false, set),
+        };
+        let mut found = false;
+        let mut i = 0;
+        while i < set.len() {
+            if i + 2 < set.len() && set[i + 1] == '-' {
+                found |= set[i] <= c && c <= set[i + 2];
+                i += 3;
+            } else {
+                found |= set[i] == c;
+                i += 1;
+            }
+        }
+        found != negated
+    }
+
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches(&pattern, &name)
+}
+
+/// How commands changing the filesystem go about it.
+#[derive(Clone, Debug, Default)]
+pub struct ChangeMode {
+    /// Only report the planned changes, leaving the disk untouched.
+    pub dry_run: bool,
+    /// Move removed files to `trash_dir` instead of deleting them.
+    pub trash: bool,
+    /// Where removed files go in trash mode and are restored from.
+    pub trash_dir: PathBuf,
+}
+
+impl ChangeMode {
+    /// Change mode making changes right away, with the trash directory
+    /// at `$SHELL_TRASH`, or `~/.shell_trash`.
+    pub fn from_env() -> ChangeMode {
+        let trash_dir = match std::env::var_os("SHELL_TRASH") {
+            Some(dir) => PathBuf::from(dir),
+            None => std::env::var_os("HOME")
+                .map(PathBuf::from)
+                .unwrap_or_else(std::env::temp_dir)
+                .join(".shell_trash"),
+        };
+        ChangeMode {
+            dry_run: false,
+            trash: false,
+            trash_dir,
+        }
+    }
+}
+
+/// A single change to the filesystem, planned by a command before any of
+/// its changes are made.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileAction {
+    /// Copy(source, target)
+    Copy(PathBuf, PathBuf),
+    /// Move(source, target)
+    Move(PathBuf, PathBuf),
+    Remove(PathBuf),
+    /// Trash(path, trash directory)
+    Trash(PathBuf, PathBuf),
+    /// CreateDir(path, parents)
+    CreateDir(PathBuf, bool),
+    Touch(PathBuf),
+    /// Restore(file in the trash, original path)
+    Restore(PathBuf, PathBuf),
+}
+
+impl fmt::Display for FileAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileAction::Copy(source, target) => {
+                write!(f, "copy {} -> {}", source.display(), target.display())
+            }
+            FileAction::Move(source, target) => {
+                write!(f, "move {} -> {}", source.display(), target.display())
+            }
+            FileAction::Remove(path) => write!(f, "remove {}", path.display()),
+            FileAction::Trash(path, _) => write!(f, "trash {}", path.display()),
+            FileAction::CreateDir(path, _) => write!(f, "create directory {}", path.display()),
+            FileAction::Touch(path) => write!(f, "touch {}", path.display()),
+            FileAction::Restore(trashed, path) => {
+                write!(f, "restore {} -> {}", trashed.display(), path.display())
+            }
+        }
+    }
+}
+
+impl FileAction {
+    /// Makes this change on disk.
+    pub fn apply(&self) -> Result<(), ShellError> {
+        match self {
+            FileAction::Copy(source, target) => copy_recursive(source, target),
+            FileAction::Move(source, target) => move_path(source, target),
+            FileAction::Remove(path) => remove_path(path),
+            FileAction::Trash(path, trash_dir) => trash_path(path, trash_dir),
+            FileAction::CreateDir(path, true) => {
+                std::fs::create_dir_all(path).map_err(path_error(path))
+            }
+            FileAction::CreateDir(path, false) => {
+                std::fs::create_dir(path).map_err(path_error(path))
+            }
+            FileAction::Touch(path) => {
+                let file = File::options()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(path_error(path))?;
+                file.set_modified(SystemTime::now())
+                    .map_err(path_error(path))
+            }
+            FileAction::Restore(trashed, path) => {
+                if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                    std::fs::create_dir_all(parent).map_err(path_error(parent))?;
+                }
+                move_path(trashed, path)?;
+                let info = trash_info_path(trashed);
+                std::fs::remove_file(&info).map_err(path_error(&info))
+            }
+        }
+    }
+}
+
+/// Plans a change for every path with `plan`, carrying on when it fails for
+/// some of them, then makes the planned changes. In dry-run mode the
+/// planned changes are returned instead.
+/// Fails with the error of the path that failed, or all of them if several
+/// did.
+fn change_each_path<F>(
+    paths: &[PathBuf],
+    mode: &ChangeMode,
+    mut plan: F,
+) -> Result<CommandOutput, ShellError>
+where
+    F: FnMut(&Path) -> Result<Option<FileAction>, ShellError>,
+{
+    let mut actions = vec![];
+    let mut errors = vec![];
+    for path in paths {
+        match plan(path) {
+            Ok(action) => actions.extend(action),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    let output = if mode.dry_run {
+        CommandOutput::List(actions.iter().map(|a| a.to_string()).collect())
+    } else {
+        errors.extend(actions.iter().filter_map(|a| a.apply().err()));
+        CommandOutput::None
+    };
+    match errors.len() {
+        0 => Ok(output),
+        1 => Err(errors.remove(0)),
+        _ => Err(ShellError::MultipleErrors(errors)),
+    }
+}
+
+/// Attaches `path` to an io::Error so we know which file it is about.
+fn path_error(path: &Path) -> impl FnOnce(io::Error) -> ShellError + '_ {
+    move |e| ShellError::PathError(path.to_path_buf(), e)
+}
+
+/// Fails if nothing exists at `path`. Broken symlinks do exist.
+fn check_exists(path: &Path) -> Result<(), ShellError> {
+    std::fs::symlink_metadata(path)
+        .map(|_| ())
+        .map_err(path_error(path))
+}
+
+/// Where `source` ends up when copied or moved to `dest`: inside `dest` if
+/// it is a directory, `dest` itself otherwise.
+fn destination(source: &Path, dest: &Path) -> PathBuf {
+    match source.file_name() {
+        Some(name) if dest.is_dir() => dest.join(name),
+        _ => dest.to_path_buf(),
+    }
+}
+
+/// Checks there is somewhere to put several `sources`.
+fn check_destination(sources: &[PathBuf], dest: &Path) -> Result<(), ShellError> {
+    if sources.len() > 1 && !dest.is_dir() {
+        let err_msg = "Destination of several files must be a directory";
+        return Err(ShellError::PathError(
+            dest.to_path_buf(),
+            io::Error::new(ErrorKind::InvalidInput, err_msg),
+        ));
+    }
+    Ok(())
+}
+
+/// Copies `sources` to `dest`. Directories are only copied if `recursive`.
+pub fn cp(
+    sources: &[PathBuf],
+    dest: &Path,
+    recursive: bool,
+    mode: &ChangeMode,
+) -> Result<CommandOutput, ShellError> {
+    let sources = expand_globs(sources);
+    check_destination(&sources, dest)?;
+    change_each_path(&sources, mode, |source| {
+        check_exists(source)?;
+        if source.is_dir() && !recursive {
+            let err_msg = "Is a directory, use cp -r to copy it";
+            return Err(ShellError::PathError(
+                source.to_path_buf(),
+                io::Error::new(ErrorKind::InvalidInput, err_msg),
+            ));
+        }
+        let target = destination(source, dest);
+        if source.is_dir() && is_inside(&target, source) {
+            let err_msg = "Cannot copy a directory into itself";
+            return Err(ShellError::PathError(
+                source.to_path_buf(),
+                io::Error::new(ErrorKind::InvalidInput, err_msg),
+            ));
+        }
+        Ok(Some(FileAction::Copy(source.to_path_buf(), target)))
+    })
+}
+
+/// Whether `path` is `dir` or somewhere inside it, once both are resolved
+/// to absolute paths without symlinks. The part of `path` that does not
+/// exist yet is taken as it is.
+fn is_inside(path: &Path, dir: &Path) -> bool {
+    let dir = match dir.canonicalize() {
+        Ok(dir) => dir,
+        Err(_) => return false,
+    };
+    let mut existing = path;
+    let mut missing = vec![];
+    loop {
+        if let Ok(resolved) = existing.canonicalize() {
+            let path = missing
+                .iter()
+                .rev()
+                .fold(resolved, |path, name| path.join(name));
+            return path.starts_with(&dir);
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = if parent.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    parent
+                };
+            }
+            _ => return false,
+        }
+    }
+}
+
+fn copy_recursive(source: &Path, target: &Path) -> Result<(), ShellError> {
+    if !source.is_dir() {
+        std::fs::copy(source, target).map_err(path_error(source))?;
+        return Ok(());
+    }
+
+    std::fs::create_dir_all(target).map_err(path_error(target))?;
+    for entry in std::fs::read_dir(source).map_err(path_error(source))? {
+        let entry = entry.map_err(path_error(source))?;
+        copy_recursive(&entry.path(), &target.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+/// Moves `sources` to `dest`.
+pub fn mv(
+    sources: &[PathBuf],
+    dest: &Path,
+    mode: &ChangeMode,
+) -> Result<CommandOutput, ShellError> {
+    let sources = expand_globs(sources);
+    check_destination(&sources, dest)?;
+    change_each_path(&sources, mode, |source| {
+        check_exists(source)?;
+        let target = destination(source, dest);
+        Ok(Some(FileAction::Move(source.to_path_buf(), target)))
+    })
+}
+
+/// Renames `source` to `target`.
+/// Falls back to copying then removing when moving across filesystems.
+fn move_path(source: &Path, target: &Path) -> Result<(), ShellError> {
+    match std::fs::rename(source, target) {
+        Err(e) if e.raw_os_error() == Some(libc::EXDEV) => {
+            copy_recursive(source, target)?;
+            remove_path(source)
+        }
+        result => result.map_err(path_error(source)),
+    }
+}
+
+fn remove_path(path: &Path) -> Result<(), ShellError> {
+    if path.is_dir() && !path.is_symlink() {
+        std::fs::remove_dir_all(path).map_err(path_error(path))
+    } else {
+        std::fs::remove_file(path).map_err(path_error(path))
+    }
+}
+
+/// Removes `paths`, or moves them to the trash in trash mode.
+/// Directories are only removed if `recursive`.
+/// Refuses to recursively remove `/` or the home directory unless told
+/// otherwise with `no_preserve_root`, and `.` or `..` in any case.
+pub fn rm(
+    paths: &[PathBuf],
+    options: &RmOptions,
+    mode: &ChangeMode,
+) -> Result<CommandOutput, ShellError> {
+    let paths = expand_globs(paths);
+    change_each_path(&paths, mode, |path| {
+        if !path.exists() && !path.is_symlink() {
+            if options.force {
+                return Ok(None);
+            }
+            return Err(ShellError::PathError(
+                path.to_path_buf(),
+                io::Error::from(ErrorKind::NotFound),
+            ));
+        }
+        if is_dot_or_dot_dot(path) {
+            let err_msg = "refusing to remove '.' or '..' directory";
+            return Err(ShellError::PathError(
+                path.to_path_buf(),
+                io::Error::new(ErrorKind::InvalidInput, err_msg),
+            ));
+        }
+        if path.is_dir() && !path.is_symlink() {
+            if !options.recursive {
+                let err_msg = "Is a directory, use rm -r to remove it";
+                return Err(ShellError::PathError(
+                    path.to_path_buf(),
+                    io::Error::new(ErrorKind::InvalidInput, err_msg),
+                ));
+            }
+            if !options.no_preserve_root && is_protected(path) {
+                return Err(ShellError::ProtectedPath(path.to_path_buf()));
+            }
+        }
+        if mode.trash {
+            let trash_dir = mode.trash_dir.clone();
+            return Ok(Some(FileAction::Trash(path.to_path_buf(), trash_dir)));
+        }
+        Ok(Some(FileAction::Remove(path.to_path_buf())))
+    })
+}
+
+/// Whether `path` is the root or the home directory.
+fn is_protected(path: &Path) -> bool {
+    let path = match path.canonicalize() {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    let home = std::env::var_os("HOME").and_then(|home| Path::new(&home).canonicalize().ok());
+    path.parent().is_none() || Some(&path) == home.as_ref()
+}
+
+/// Whether the last component of `path` is `.` or `..`, as given: `Path`
+/// leaves out a trailing `.`.
+fn is_dot_or_dot_dot(path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    let last = path.trim_end_matches('/').rsplit('/').next();
+    matches!(last, Some("." | ".."))
+}
+
+/// Moves `path` into `trash_dir/files`, recording where it came from and
+/// when in `trash_dir/info/<name>.trashinfo`. Names already in the trash
+/// get a `.1`, `.2`, ... suffix.
+fn trash_path(path: &Path, trash_dir: &Path) -> Result<(), ShellError> {
+    let files = trash_dir.join("files");
+    let info = trash_dir.join("info");
+    std::fs::create_dir_all(&files).map_err(path_error(&files))?;
+    std::fs::create_dir_all(&info).map_err(path_error(&info))?;
+
+    let original = absolute_path(path).map_err(path_error(path))?;
+    let name = original
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "root".to_string());
+    let mut trashed = files.join(&name);
+    let mut suffix = 0;
+    while trashed.symlink_metadata().is_ok() || trash_info_path(&trashed).exists() {
+        suffix += 1;
+        trashed = files.join(format!("{}.{}", name, suffix));
+    }
+
+    let deleted = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let info_path = trash_info_path(&trashed);
+    let contents = format!(
+        "[Trash Info]\nPath={}\nDeletionTime={}\n",
+        original.display(),
+        deleted
+    );
+    std::fs::write(&info_path, contents).map_err(path_error(&info_path))?;
+    move_path(path, &trashed).inspect_err(|_| {
+        let _ = std::fs::remove_file(&info_path);
+    })
+}
+
+/// Absolute version of `path`. Only its parent is resolved, so a symlink
+/// stays a path to the symlink.
+fn absolute_path(path: &Path) -> io::Result<PathBuf> {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            Ok(parent.canonicalize()?.join(name))
+        }
+        _ => path.canonicalize(),
+    }
+}
+
+/// The file describing `trashed`, a file in the `files` directory of a trash.
+fn trash_info_path(trashed: &Path) -> PathBuf {
+    let mut name = trashed.file_name().unwrap_or_default().to_os_string();
+    name.push(".trashinfo");
+    let trash_dir = trashed.parent().and_then(Path::parent).unwrap_or(trashed);
+    trash_dir.join("info").join(name)
+}
+
+/// Files in the trash at `trash_dir`, as (name, original path, deletion
+/// time in seconds since the Unix epoch), sorted by name.
+fn trash_entries(trash_dir: &Path) -> Result<Vec<(String, PathBuf, u64)>, ShellError> {
+    let info = trash_dir.join("info");
+    if !info.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut entries = vec![];
+    for entry in std::fs::read_dir(&info).map_err(path_error(&info))? {
+        let path = entry.map_err(path_error(&info))?.path();
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => match name.strip_suffix(".trashinfo") {
+                Some(name) => name.to_string(),
+                None => continue,
+            },
+            None => continue,
+        };
+        let contents = std::fs::read_to_string(&path).map_err(path_error(&path))?;
+        let mut original = None;
+        let mut deleted = 0;
+        for line in contents.lines() {
+            if let Some(p) = line.strip_prefix("Path=") {
+                original = Some(PathBuf::from(p));
+            } else if let Some(t) = line.strip_prefix("DeletionTime=") {
+                deleted = t.parse().unwrap_or(0);
+            }
+        }
+        if let Some(original) = original {
+            entries.push((name, original, deleted));
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+/// Puts a file back where it was before being moved to the trash. `name`
+/// is either its name in the trash or its original path; when several
+/// trashed files came from that path the last one removed is restored.
+/// Without a `name` lists the contents of the trash. An original path is
+/// relative to `cwd`.
+pub fn restore(
+    name: Option<&str>,
+    cwd: &Path,
+    mode: &ChangeMode,
+) -> Result<CommandOutput, ShellError> {
+    let entries = trash_entries(&mode.trash_dir)?;
+    let name = match name {
+        Some(name) => name,
+        None => {
+            let columns = ["name", "path", "deleted"].iter().map(|c| c.to_string());
+            let rows = entries
+                .into_iter()
+                .map(|(name, path, deleted)| {
+                    vec![name, path.display().to_string(), deleted.to_string()]
+                })
+                .collect();
+            return Ok(CommandOutput::Table(columns.collect(), rows));
+        }
+    };
+
+    let absolute = absolute_path(&cwd.join(name)).ok();
+    let entry = entries
+        .into_iter()
+        .filter(|(trashed, original, _)| trashed == name || Some(original) == absolute.as_ref())
+        .max_by_key(|(_, _, deleted)| *deleted);
+    let (trashed, original) = match entry {
+        Some((trashed, original, _)) => (mode.trash_dir.join("files").join(trashed), original),
+        None => {
+            return Err(ShellError::PathError(
+                PathBuf::from(name),
+                io::Error::new(ErrorKind::NotFound, "Not in the trash"),
+            ))
+        }
+    };
+    if original.symlink_metadata().is_ok() {
+        return Err(ShellError::PathError(
+            original,
+            io::Error::from(ErrorKind::AlreadyExists),
+        ));
+    }
+
+    let action = FileAction::Restore(trashed, original);
+    if mode.dry_run {
+        return Ok(CommandOutput::List(vec![action.to_string()]));
+    }
+    action.apply()?;
+    Ok(CommandOutput::None)
+}
+
+/// Creates the directories `paths`, along with their missing parents if
+/// `parents`. With `parents` existing directories are not an error.
+pub fn mkdir(
+    paths: &[PathBuf],
+    parents: bool,
+    mode: &ChangeMode,
+) -> Result<CommandOutput, ShellError> {
+    change_each_path(paths, mode, |path| {
+        if parents && path.is_dir() {
+            return Ok(None);
+        }
+        if !parents && path.symlink_metadata().is_ok() {
+            return Err(ShellError::PathError(
+                path.to_path_buf(),
+                io::Error::from(ErrorKind::AlreadyExists),
+            ));
+        }
+        Ok(Some(FileAction::CreateDir(path.to_path_buf(), parents)))
+    })
+}
+
+/// Creates the files `paths` if they do not exist, otherwise sets their
+/// modification time to now.
+pub fn touch(paths: &[PathBuf], mode: &ChangeMode) -> Result<CommandOutput, ShellError> {
+    let paths = expand_globs(paths);
+    change_each_path(&paths, mode, |path| {
+        Ok(Some(FileAction::Touch(path.to_path_buf())))
+    })
+}
 
 /// Verify contents of file match our more command.
 #[test]
@@ -119,10 +2178,10 @@ fn cat_file_test_one_file() {
     let test_file = "./resources/cat_file_test.txt";
     assert_eq!(
         more_file(test_file).unwrap(),
-        cat_files(&[PathBuf::from(test_file)]).
-            unwrap().
-            to_string().
-            unwrap()
+        cat_files(&[PathBuf::from(test_file)], &CancellationToken::default())
+            .unwrap()
+            .to_string()
+            .unwrap()
     );
 }
 
@@ -138,9 +2197,555 @@ fn cat_file_test() {
     manual.push_str(&more_file(test_file2).unwrap());
     assert_eq!(
         manual,
-        cat_files(paths).
-            unwrap().
-            to_string().
-            unwrap()
+        cat_files(paths, &CancellationToken::default())
+            .unwrap()
+            .to_string()
+            .unwrap()
+    );
+}
+
+/// Long-running commands stop with Interrupted once their token is
+/// cancelled.
+#[test]
+fn interrupted_test() {
+    let token = CancellationToken::default();
+    let paths = &[PathBuf::from("./resources/cat_file_test.txt")];
+    assert!(cat_files(paths, &token).is_ok());
+    token.cancel();
+    let interrupted = |result| matches!(result, Err(ShellError::Interrupted));
+    assert!(interrupted(cat_files(paths, &token)));
+    assert!(interrupted(find_file("./resources", "fruits.txt", &token)));
+    assert!(interrupted(find_string("a\nb", "a", &token)));
+}
+
+/// Binary files are passed through unchanged by cat.
+#[test]
+fn cat_binary_file_test() {
+    let test_file = "./resources/binary_file.bin";
+    match cat_files(&[PathBuf::from(test_file)], &CancellationToken::default()).unwrap() {
+        CommandOutput::Bytes(bytes) => {
+            assert_eq!(
+                bytes,
+                include_bytes!("../resources/binary_file.bin").to_vec()
+            )
+        }
+        output => panic!("expected bytes, got {:?}", output),
+    }
+}
+
+/// Binary files are shown as a hexdump instead of failing.
+#[test]
+fn more_binary_file_test() {
+    let test_file = "./resources/binary_file.bin";
+    assert!(more_file(test_file).is_err());
+
+    let output = more_or_hexdump(test_file).unwrap().to_string().unwrap();
+    assert!(output.starts_with(
+        "00000000  7f 45 4c 46 02 01 00 ff fe 6f 6d 61 72 20 69 73  |.ELF.....omar is|"
+    ));
+}
+
+/// Head and tail of a file match head and tail of its contents.
+#[test]
+fn head_tail_file_test() {
+    let test_files = [PathBuf::from("./resources/cat_file_test.txt")];
+    let contents = more_file(&test_files[0]).unwrap();
+
+    let head = head_files(&test_files, 2).unwrap().to_string();
+    assert_eq!(head, Some("omar\nis".to_string()));
+    assert_eq!(head, head_lines(&contents, 2).to_string());
+
+    let tail = tail_files(&test_files, 2).unwrap().to_string();
+    assert_eq!(tail, Some("is\ncool".to_string()));
+    assert_eq!(tail, tail_lines(&contents, 2).to_string());
+
+    let all = tail_files(&test_files, 10).unwrap().to_string();
+    assert_eq!(all, Some("omar\nis\ncool".to_string()));
+
+    // Both replace invalid UTF-8 the same way.
+    let binary = [PathBuf::from("./resources/binary_file.bin")];
+    let head = head_files(&binary, 100).unwrap().to_string();
+    assert_eq!(head, tail_files(&binary, 100).unwrap().to_string());
+    assert!(head.unwrap().contains('\u{fffd}'));
+}
+
+/// Tail of a file spanning several blocks.
+#[test]
+fn tail_large_file_test() {
+    let paths = [std::env::temp_dir().join("shell_tail_large_file_test.txt")];
+    let lines: Vec<String> = (0..3000).map(|i| format!("line {}", i)).collect();
+    std::fs::write(&paths[0], lines.join("\n") + "\n").unwrap();
+
+    let tail = tail_files(&paths, 1500).unwrap().to_string().unwrap();
+    assert_eq!(tail, lines[1500..].join("\n"));
+    std::fs::remove_file(&paths[0]).unwrap();
+}
+
+/// Counts of the fruits fixture.
+#[test]
+fn wc_test() {
+    let fruits = [PathBuf::from("./resources/fruits.txt")];
+    let content = more_file(&fruits[0]).unwrap();
+
+    match wc(&content, &WcOptions::default()) {
+        CommandOutput::Table(columns, rows) => {
+            assert_eq!(columns, vec!["lines", "words", "bytes", "chars"]);
+            assert_eq!(rows, vec![vec!["5", "15", "68", "68"]]);
+        }
+        output => panic!("expected a table, got {:?}", output),
+    }
+
+    let options = WcOptions {
+        lines: true,
+        ..WcOptions::default()
+    };
+    let output = wc_files(&fruits, &options).unwrap().to_string();
+    assert_eq!(output, Some("5\t./resources/fruits.txt".to_string()));
+}
+
+/// Sorting as text, numerically on a key, reversed and unique.
+#[test]
+fn sort_test() {
+    let content = more_file("./resources/fruits.txt").unwrap();
+
+    let sorted = sort_lines(&content, &SortOptions::default()).to_string();
+    let expected = "apple 10 red\napple 10 red\nbanana 3 yellow\ncherry 7 red\ndate 1 brown";
+    assert_eq!(sorted, Some(expected.to_string()));
+
+    let options = SortOptions {
+        numeric: true,
+        reverse: true,
+        key: Some(2),
+        unique: true,
+    };
+    let sorted = sort_lines(&content, &options).to_string();
+    let expected = "apple 10 red\ncherry 7 red\nbanana 3 yellow\ndate 1 brown";
+    assert_eq!(sorted, Some(expected.to_string()));
+}
+
+/// Only adjacent duplicates are collapsed.
+#[test]
+fn uniq_test() {
+    let content = more_file("./resources/fruits.txt").unwrap();
+    let sorted = sort_lines(&content, &SortOptions::default())
+        .to_string()
+        .unwrap();
+
+    let counted = uniq_lines(&sorted, true).to_string().unwrap();
+    assert!(counted.starts_with("2\tapple 10 red\n1\tbanana 3 yellow"));
+    assert_eq!(
+        uniq_lines(&content, false)
+            .to_string()
+            .unwrap()
+            .lines()
+            .count(),
+        5
     );
 }
+
+/// Cutting fields and translating characters.
+#[test]
+fn cut_tr_test() {
+    let content = more_file("./resources/fruits.txt").unwrap();
+    let head = head_lines(&content, 2).to_string().unwrap();
+
+    let cut = cut_fields(&head, ' ', &[(1, 1), (3, usize::MAX)]).to_string();
+    assert_eq!(cut, Some("banana yellow\napple red".to_string()));
+
+    let upper = translate(&head, "a-z", Some("A-Z")).to_string();
+    assert_eq!(upper, Some("BANANA 3 YELLOW\nAPPLE 10 RED".to_string()));
+    let deleted = translate(&head, "0-9 ", None).to_string();
+    assert_eq!(deleted, Some("bananayellow\napplered".to_string()));
+}
+
+/// Wildcards match file names in the fixtures.
+#[test]
+fn expand_globs_test() {
+    let globbed = expand_globs(&[
+        PathBuf::from("resources/*.txt"),
+        PathBuf::from("resources/test_dir/[a-z]*/d?mmy.txt"),
+        PathBuf::from("resources/nothing*"),
+    ]);
+    let expected: Vec<PathBuf> = [
+        "resources/cat_file_test.txt",
+        "resources/fruits.txt",
+        "resources/test_dir/test_dir2/dummy.txt",
+        "resources/nothing*",
+    ]
+    .iter()
+    .map(PathBuf::from)
+    .collect();
+    assert_eq!(globbed, expected);
+    assert!(glob_matches("[!a]*.rs", "main.rs"));
+    assert!(!glob_matches("[!m]*.rs", "main.rs"));
+}
+
+/// Copying, moving, touching and removing files in a scratch directory.
+#[test]
+fn file_manipulation_test() {
+    let dir = std::env::temp_dir().join("shell_file_manipulation_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    let mode = ChangeMode::default();
+    mkdir(&[dir.join("a/b")], true, &mode).unwrap();
+    assert!(mkdir(&[dir.join("a/b")], false, &mode).is_err());
+
+    touch(&[dir.join("a/b/new.txt")], &mode).unwrap();
+    cp(
+        &[PathBuf::from("resources/test_dir")],
+        &dir.join("a"),
+        true,
+        &mode,
+    )
+    .unwrap();
+    assert!(dir.join("a/test_dir/test_dir2/dummy.txt").is_file());
+    assert!(cp(&[PathBuf::from("resources/test_dir")], &dir, false, &mode).is_err());
+    // Not even through `.` or `..`, which would copy forever.
+    assert!(cp(&[dir.join("a/.")], &dir.join("a/b/../copy"), true, &mode).is_err());
+    assert!(!dir.join("a/copy").exists());
+
+    mv(&[dir.join("a/b/*.txt")], &dir, &mode).unwrap();
+    assert!(dir.join("new.txt").is_file());
+    assert!(!dir.join("a/b/new.txt").exists());
+
+    let missing = [dir.join("missing.txt"), dir.join("also_missing.txt")];
+    match rm(&missing, &RmOptions::default(), &mode) {
+        Err(ShellError::MultipleErrors(errors)) => assert_eq!(errors.len(), 2),
+        result => panic!("expected two errors, got {:?}", result),
+    }
+    let force = RmOptions {
+        force: true,
+        ..RmOptions::default()
+    };
+    rm(&missing, &force, &mode).unwrap();
+
+    assert!(rm(&[dir.join("a")], &RmOptions::default(), &mode).is_err());
+    let recursive = RmOptions {
+        recursive: true,
+        ..RmOptions::default()
+    };
+    rm(std::slice::from_ref(&dir), &recursive, &mode).unwrap();
+    assert!(!dir.exists());
+}
+
+/// rm -r refuses the root and the home directory however they are
+/// spelled, and any `.` or `..`. Other directories are fine.
+#[test]
+fn is_protected_test() {
+    assert!(is_protected(Path::new("/")));
+    assert!(is_protected(Path::new("/tmp/..")));
+    if let Some(home) = std::env::var_os("HOME") {
+        assert!(is_protected(&Path::new(&home).join(".")));
+    }
+    assert!(!is_protected(&std::env::temp_dir()));
+    assert!(!is_protected(Path::new("resources")));
+
+    for path in [".", "..", "./", "resources/.", "resources/../", "/tmp/.."] {
+        assert!(is_dot_or_dot_dot(Path::new(path)), "{}", path);
+    }
+    for path in ["resources", "..a", "a.", "/tmp/.hidden", "/"] {
+        assert!(!is_dot_or_dot_dot(Path::new(path)), "{}", path);
+    }
+
+    let dir = std::env::temp_dir().join("shell_is_protected_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("a")).unwrap();
+    let options = RmOptions {
+        recursive: true,
+        no_preserve_root: true,
+        ..RmOptions::default()
+    };
+    for path in [dir.join("a/.."), dir.join("a/.")] {
+        assert!(rm(&[path], &options, &ChangeMode::default()).is_err());
+    }
+    assert!(dir.join("a").is_dir());
+}
+
+/// Dry runs list the planned changes and leave the disk alone.
+#[test]
+fn dry_run_test() {
+    let dir = std::env::temp_dir().join("shell_dry_run_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    let dry_run = ChangeMode {
+        dry_run: true,
+        ..ChangeMode::default()
+    };
+    let new_dir = dir.join("new");
+    match mkdir(std::slice::from_ref(&new_dir), true, &dry_run) {
+        Ok(CommandOutput::List(actions)) => {
+            assert_eq!(
+                actions,
+                vec![format!("create directory {}", new_dir.display())]
+            )
+        }
+        result => panic!("expected planned actions, got {:?}", result),
+    }
+    assert!(!dir.exists());
+
+    let fruits = PathBuf::from("resources/fruits.txt");
+    match rm(
+        std::slice::from_ref(&fruits),
+        &RmOptions::default(),
+        &dry_run,
+    ) {
+        Ok(CommandOutput::List(actions)) => {
+            assert_eq!(actions, vec!["remove resources/fruits.txt"])
+        }
+        result => panic!("expected planned actions, got {:?}", result),
+    }
+    assert!(fruits.is_file());
+    assert!(rm(&[dir.join("missing.txt")], &RmOptions::default(), &dry_run).is_err());
+}
+
+/// Removed files go to the trash and can be put back.
+#[test]
+fn trash_restore_test() {
+    let dir = std::env::temp_dir().join("shell_trash_restore_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    let mode = ChangeMode {
+        trash: true,
+        trash_dir: dir.join("trash"),
+        ..ChangeMode::default()
+    };
+    let file = dir.join("data.txt");
+    mkdir(std::slice::from_ref(&dir), true, &mode).unwrap();
+    std::fs::write(&file, "first").unwrap();
+    rm(std::slice::from_ref(&file), &RmOptions::default(), &mode).unwrap();
+    std::fs::write(&file, "second").unwrap();
+    rm(std::slice::from_ref(&file), &RmOptions::default(), &mode).unwrap();
+    assert!(!file.exists());
+
+    match restore(None, Path::new("."), &mode) {
+        Ok(CommandOutput::Table(_, rows)) => {
+            let names: Vec<&str> = rows.iter().map(|row| row[0].as_str()).collect();
+            assert_eq!(names, vec!["data.txt", "data.txt.1"]);
+        }
+        result => panic!("expected the trash contents, got {:?}", result),
+    }
+
+    restore(Some("data.txt"), Path::new("."), &mode).unwrap();
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "first");
+    assert!(restore(Some("data.txt.1"), Path::new("."), &mode).is_err());
+    std::fs::remove_file(&file).unwrap();
+    restore(file.to_str(), Path::new("."), &mode).unwrap();
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "second");
+    assert!(!mode.trash_dir.join("info/data.txt.1.trashinfo").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Trees are drawn in name order, deeper levels can be left out.
+#[test]
+fn tree_test() {
+    let dir = PathBuf::from("resources/test_dir");
+    let lines = |output: CommandOutput| output.lines();
+    assert_eq!(
+        lines(tree(&dir, None, &CancellationToken::default()).unwrap()),
+        vec![
+            "resources/test_dir",
+            "├── dummy.txt",
+            "└── test_dir2",
+            "    └── dummy.txt",
+        ]
+    );
+    assert_eq!(
+        lines(tree(&dir, Some(1), &CancellationToken::default()).unwrap()),
+        vec!["resources/test_dir", "├── dummy.txt", "└── test_dir2"]
+    );
+}
+
+/// Directories add up the sizes of everything inside them.
+#[test]
+fn du_test() {
+    let dir = std::env::temp_dir().join("shell_du_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("a/b")).unwrap();
+    std::fs::create_dir_all(dir.join("c")).unwrap();
+    std::fs::write(dir.join("a/one"), vec![0; 1000]).unwrap();
+    std::fs::write(dir.join("a/b/two"), vec![0; 2000]).unwrap();
+    std::fs::write(dir.join("c/three"), vec![0; 3000]).unwrap();
+
+    let sizes = du(&dir, &DuOptions::default(), &CancellationToken::default())
+        .unwrap()
+        .lines();
+    let d = dir.display();
+    assert_eq!(
+        sizes,
+        vec![
+            format!("2000\t{}/a/b", d),
+            format!("3000\t{}/a", d),
+            format!("3000\t{}/c", d),
+            format!("6000\t{}", d),
+        ]
+    );
+    let options = DuOptions {
+        summarize: true,
+        human: true,
+    };
+    assert_eq!(
+        du(&dir, &options, &CancellationToken::default())
+            .unwrap()
+            .lines(),
+        vec![format!("5.9K\t{}", d)]
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Changes are grouped in hunks with the requested context around them.
+#[test]
+fn diff_test() {
+    let labels = ("old".to_string(), "new".to_string());
+    let old = "a\nb\nc\nd\ne\nf\ng\nh\n";
+    let new = "a\nB\nc\nd\ne\nf\ng\n";
+    assert_eq!(
+        diff(old, new, &labels, 1).to_string().unwrap(),
+        "--- old\n+++ new\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n@@ -7,2 +7 @@\n g\n-h"
+    );
+    assert_eq!(
+        diff(old, new, &labels, 3).lines()[2..4],
+        ["@@ -1,8 +1,7 @@", " a"]
+    );
+    assert_eq!(
+        diff("", "x", &labels, 3).lines()[2..],
+        ["@@ -0,0 +1 @@", "+x", "\\ No newline at end of file"]
+    );
+    assert!(matches!(diff(old, old, &labels, 3), CommandOutput::None));
+}
+
+/// Skipped bytes move the offsets, and reversing a hexdump in either layout
+/// gives back the original bytes.
+#[test]
+fn hexdump_test() {
+    let contents = read_file("./resources/binary_file.bin").unwrap();
+    let options = HexdumpOptions {
+        skip: 9,
+        length: Some(4),
+        ..HexdumpOptions::default()
+    };
+    assert_eq!(
+        hexdump_bytes(&contents, &options)
+            .unwrap()
+            .to_string()
+            .unwrap(),
+        format!("00000009  6f 6d 61 72{}  |omar|", " ".repeat(36))
+    );
+
+    let dump = hexdump(&contents, 0).join("\n");
+    assert_eq!(unhexdump(&dump).unwrap(), contents);
+    assert_eq!(unhexdump("00000004  41 42").unwrap(), b"\0\0\0\0AB");
+    assert!(unhexdump("00000000  zz").is_err());
+
+    let options = HexdumpOptions {
+        xxd: true,
+        ..options
+    };
+    assert_eq!(
+        hexdump_bytes(&contents, &options)
+            .unwrap()
+            .to_string()
+            .unwrap(),
+        format!("00000009: 6f6d 6172{}  omar", " ".repeat(30))
+    );
+    let dump = xxd(&contents, 0).join("\n");
+    assert_eq!(unhexdump(&dump).unwrap(), contents);
+    // What xxd itself prints, the ASCII column may start with spaces.
+    assert_eq!(
+        unhexdump("00000000: 7f45 4c46 0201 20                         .ELF.. ").unwrap(),
+        b"\x7fELF\x02\x01 "
+    );
+    assert!(unhexdump("00000000: 7f4").is_err());
+
+    // Output of `hexdump -C` and `xxd -a`, which squeeze repeated rows.
+    let zeros = "00000000  00 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |................|";
+    assert_eq!(
+        unhexdump(&format!("{}\n*\n00000040\n", zeros)).unwrap(),
+        vec![0; 64]
+    );
+    let text = format!(
+        "{}\n*\n00000040  61 62{}|ab|\n00000042\n",
+        zeros,
+        " ".repeat(45)
+    );
+    let mut zeros_ab = vec![0; 64];
+    zeros_ab.extend_from_slice(b"ab");
+    assert_eq!(unhexdump(&text).unwrap(), zeros_ab);
+    let zeros = "00000000: 0000 0000 0000 0000 0000 0000 0000 0000  ................";
+    let text = format!("{}\n*\n00000030: {}", zeros, &zeros[10..]);
+    assert_eq!(unhexdump(&text).unwrap(), vec![0; 64]);
+    let text = format!("{}\n*\n00000040: 6162{}ab\n", zeros, " ".repeat(37));
+    assert_eq!(unhexdump(&text).unwrap(), zeros_ab);
+}
+
+/// Files are recognised by their magic bytes, metadata is one row per path.
+#[test]
+fn stat_file_test() {
+    let paths = [
+        PathBuf::from("resources/binary_file.bin"),
+        PathBuf::from("resources/fruits.txt"),
+        PathBuf::from("resources/test_dir"),
+    ];
+    match file_types(&paths).unwrap() {
+        CommandOutput::Table(_, rows) => {
+            let types: Vec<&str> = rows.iter().map(|row| row[1].as_str()).collect();
+            assert_eq!(types, vec!["ELF executable", "ASCII text", "directory"]);
+        }
+        output => panic!("expected a table, got {:?}", output),
+    }
+    assert_eq!(sniff(b"\x1f\x8b\x08"), "gzip compressed data");
+    assert_eq!(sniff("caf\u{e9}".as_bytes()), "UTF-8 text");
+
+    match stat(&paths[1..2]).unwrap() {
+        CommandOutput::Table(columns, rows) => {
+            assert_eq!(columns[..3], ["file", "type", "size"]);
+            assert_eq!(rows[0][..3], ["resources/fruits.txt", "regular file", "68"]);
+        }
+        output => panic!("expected a table, got {:?}", output),
+    }
+    assert_eq!(format_time(0), "1970-01-01 00:00:00");
+    assert_eq!(format_time(1_700_000_000), "2023-11-14 22:13:20");
+}
+
+/// Escapes are replaced and printf formats reuse their format string.
+#[test]
+fn printf_test() {
+    assert_eq!(
+        expand_escapes(r"a\tb\x41\0101\\\q"),
+        ("a\tbAA\\\\q".to_string(), false)
+    );
+    assert_eq!(expand_escapes(r"stop\chere"), ("stop".to_string(), true));
+
+    let args: Vec<String> = ["ab", "3.14159", "-7"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let output = printf(r"[%-4s][%6.2f][%03d]\n", &args).unwrap();
+    assert_eq!(output.to_string().unwrap(), "[ab  ][  3.14][-07]\n");
+    let args: Vec<String> = ["a", "b", "c"].iter().map(|s| s.to_string()).collect();
+    assert_eq!(printf("%s,", &args).unwrap().to_string().unwrap(), "a,b,c,");
+    assert!(printf("%d", &args).is_err());
+}
+
+/// Tee writes its input as printed and hands it on, even when some files
+/// cannot be written.
+#[test]
+fn tee_test() {
+    let dir = std::env::temp_dir().join("shell_tee_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("out.txt");
+
+    let input = CommandOutput::List(vec!["a".to_string(), "b".to_string()]);
+    tee(input, std::slice::from_ref(&file), false).unwrap();
+    let input = CommandOutput::Single("c\n".to_string());
+    tee(input, std::slice::from_ref(&file), true).unwrap();
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "a\nb\nc\n");
+
+    let input = CommandOutput::Single("d".to_string());
+    match tee(input, &[file.clone(), dir.join("missing/out.txt")], false) {
+        Err(ShellError::PartialFailure(CommandOutput::Single(s), e)) => {
+            assert_eq!(s, "d");
+            assert!(matches!(*e, ShellError::PathError(_, _)));
+        }
+        result => panic!("expected a partial failure, got {:?}", result),
+    }
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "d\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,410 @@
+//! Line editor for the prompt when the shell runs on a terminal. The
+//! command line is highlighted as it is typed, split into tokens the same
+//! way the parser does.
+//! Keys are read straight from the terminal (/dev/tty) in raw mode, like
+//! in the pager.
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+
+use crate::error::{ShellError, Span};
+use crate::pager::{read_key, terminal_size, Key, RawMode};
+use crate::parser::{is_name, tokens, TokenKind};
+
+/// What the terminal loop should do after a key was handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edit {
+    Continue,
+    /// Enter was pressed, the line is complete.
+    Done,
+    /// Ctrl-C was pressed, the line is dropped.
+    Cancel,
+    /// Ctrl-D was pressed on an empty line.
+    EndOfInput,
+}
+
+/// The line being typed and the cursor in it. Knows nothing about the
+/// terminal so it can be tested on its own.
+#[derive(Default)]
+pub struct LineEditor {
+    line: String,
+    /// Byte offset of the cursor in `line`.
+    cursor: usize,
+}
+
+impl LineEditor {
+    pub fn new() -> LineEditor {
+        LineEditor::default()
+    }
+
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    /// Byte offset of the cursor in the line.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Updates the line for `key`.
+    /// Characters are inserted at the cursor, `Backspace` removes the one
+    /// before it and Ctrl-D the one after it. Left and right arrows,
+    /// `Home`/Ctrl-A and `End`/Ctrl-E move the cursor, Ctrl-U removes
+    /// everything before it.
+    pub fn handle_key(&mut self, key: Key) -> Edit {
+        match key {
+            Key::Char(c) => {
+                self.line.insert(self.cursor, c);
+                self.cursor += c.len_utf8();
+            }
+            Key::Backspace => {
+                if let Some(c) = self.line[..self.cursor].chars().next_back() {
+                    self.cursor -= c.len_utf8();
+                    self.line.remove(self.cursor);
+                }
+            }
+            Key::Left => {
+                if let Some(c) = self.line[..self.cursor].chars().next_back() {
+                    self.cursor -= c.len_utf8();
+                }
+            }
+            Key::Right => {
+                if let Some(c) = self.line[self.cursor..].chars().next() {
+                    self.cursor += c.len_utf8();
+                }
+            }
+            Key::Home | Key::Ctrl('a') => self.cursor = 0,
+            Key::End | Key::Ctrl('e') => self.cursor = self.line.len(),
+            Key::Ctrl('u') => {
+                self.line.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::Ctrl('d') if self.line.is_empty() => return Edit::EndOfInput,
+            Key::Ctrl('d') if self.cursor < self.line.len() => {
+                self.line.remove(self.cursor);
+            }
+            Key::Ctrl('c') => return Edit::Cancel,
+            Key::Enter => return Edit::Done,
+            _ => {}
+        }
+        Edit::Continue
+    }
+}
+
+/// Words starting a command, or part of a compound one, after which a
+/// command name is expected.
+const COMMAND_KEYWORDS: &[&str] = &["if", "then", "elif", "else", "do", "while", "{"];
+/// Words ending a compound command, or after which the next word is not a
+/// command name.
+const OTHER_KEYWORDS: &[&str] = &["fi", "done", "}", "for"];
+
+/// `line` with ANSI colors: names of known commands in green, unknown ones
+/// in red, keywords in bold blue, quoted strings in yellow, pipes in cyan,
+/// redirections in magenta and comments in grey. `known` tells whether a
+/// command name is a command or a function.
+pub fn highlight(line: &str, known: impl Fn(&str) -> bool) -> String {
+    let tokens = tokens(line);
+    let mut highlighted = String::new();
+    let mut end = 0;
+    let mut command = true;
+    for (i, (kind, span)) in tokens.iter().enumerate() {
+        highlighted.push_str(&line[end..span.start]);
+        end = span.end;
+        let text = word(line, *span);
+        let color = match kind {
+            TokenKind::Separator => {
+                command = true;
+                None
+            }
+            TokenKind::Pipe => {
+                command = true;
+                Some("36")
+            }
+            TokenKind::Redirect => {
+                command = false;
+                Some("35")
+            }
+            TokenKind::Paren => {
+                // A command follows `(`, and the `()` of a function.
+                command = text == "(" || i > 0 && tokens[i - 1].1.end == span.start;
+                None
+            }
+            TokenKind::Comment => Some("90"),
+            TokenKind::Word if text == "<<<" => Some("35"),
+            TokenKind::Word if command && COMMAND_KEYWORDS.contains(&text) => Some("1;34"),
+            TokenKind::Word if command && OTHER_KEYWORDS.contains(&text) => {
+                command = false;
+                Some("1;34")
+            }
+            // `in` of a `for` loop.
+            TokenKind::Word if text == "in" && i >= 2 && word(line, tokens[i - 2].1) == "for" => {
+                Some("1;34")
+            }
+            TokenKind::Word if command && text == "--lossy" => None,
+            TokenKind::Word if command => {
+                command = false;
+                // A function being defined, `name() { ... }`.
+                let defined = matches!(
+                    tokens.get(i + 1),
+                    Some((TokenKind::Paren, next)) if next.start == span.end
+                );
+                match text.split_once('=') {
+                    Some((name, _)) if is_name(name) => None,
+                    _ if text.contains(['$', '`']) => None,
+                    _ if defined || known(text) => Some("32"),
+                    _ => Some("31"),
+                }
+            }
+            TokenKind::Word if text.starts_with(['\'', '"']) => Some("33"),
+            TokenKind::Word => None,
+        };
+        match color {
+            Some(color) => highlighted.push_str(&format!("\x1b[{}m{}\x1b[0m", color, text)),
+            None => highlighted.push_str(text),
+        }
+    }
+    highlighted.push_str(&line[end..]);
+    highlighted
+}
+
+fn word(line: &str, span: Span) -> &str {
+    &line[span.start..span.end]
+}
+
+/// Number of terminal columns `c` takes: none for control characters and
+/// combining marks, two for East Asian wide characters and emoji.
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0..=0x1f | 0x7f | 0x300..=0x36f | 0x200b..=0x200f | 0xfe00..=0xfe0f => 0,
+        0x1100..=0x115f
+        | 0x2e80..=0x303e
+        | 0x3041..=0x33ff
+        | 0x3400..=0x4dbf
+        | 0x4e00..=0x9fff
+        | 0xa000..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x1f300..=0x1f64f
+        | 0x1f900..=0x1f9ff
+        | 0x20000..=0x3fffd => 2,
+        _ => 1,
+    }
+}
+
+/// Row and column, counted from where `text` starts at the beginning of a
+/// row, of the cell following it on a terminal `columns` wide. ANSI escape
+/// sequences take no room and a wide character not fitting at the end of
+/// a row goes on the next one, as terminals do.
+fn layout(text: &str, columns: usize) -> (usize, usize) {
+    let (mut row, mut column) = (0, 0);
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // `ESC [ parameters final-byte`, or ESC and one character.
+            if chars.next() == Some('[') {
+                chars.find(|c| ('@'..='~').contains(c));
+            }
+            continue;
+        }
+        let width = char_width(c);
+        if column + width > columns {
+            row += 1;
+            column = 0;
+        }
+        column += width;
+    }
+    if column == columns {
+        (row + 1, 0)
+    } else {
+        (row, column)
+    }
+}
+
+/// What to write to the terminal to redraw `prompt` followed by
+/// `highlighted`, the highlighted `line`, when the cursor is `row` rows
+/// below the start of the prompt. The cursor is then put at the byte offset
+/// `cursor` of `line`. Returns the row it ends up on along with the output.
+fn redraw(
+    prompt: &str,
+    highlighted: &str,
+    line: &str,
+    cursor: usize,
+    row: usize,
+    columns: usize,
+) -> (String, usize) {
+    let mut output = String::new();
+    if row > 0 {
+        output.push_str(&format!("\x1b[{}A", row));
+    }
+    output.push_str("\r\x1b[J");
+    output.push_str(prompt);
+    output.push_str(highlighted);
+
+    let (end_row, end_column) = layout(&format!("{}{}", prompt, line), columns);
+    if end_row > 0 && end_column == 0 {
+        // Terminals only wrap a full row on the next character, go to the
+        // next row now so the cursor is where it is counted.
+        output.push_str("\r\n");
+    }
+    let (cursor_row, cursor_column) = layout(&format!("{}{}", prompt, &line[..cursor]), columns);
+    if end_row > cursor_row {
+        output.push_str(&format!("\x1b[{}A", end_row - cursor_row));
+    }
+    output.push('\r');
+    if cursor_column > 0 {
+        output.push_str(&format!("\x1b[{}C", cursor_column));
+    }
+    (output, cursor_row)
+}
+
+/// Reads a line from the terminal after printing `prompt`, redrawing it
+/// through `highlight` as it is typed. A line longer than the terminal is
+/// wide wraps on the following rows, which are redrawn along with it.
+/// Returns None at the end of input, `ShellError::Interrupted` for Ctrl-C.
+pub fn read_line(
+    prompt: &str,
+    highlight: impl Fn(&str) -> String,
+) -> Result<Option<String>, ShellError> {
+    let mut tty = File::open("/dev/tty")?;
+    let mut stdout = io::stdout();
+    // Only the last line of the prompt is redrawn.
+    let prompt = match prompt.rsplit_once('\n') {
+        Some((above, last)) => {
+            writeln!(stdout, "{}", above)?;
+            last
+        }
+        None => prompt,
+    };
+
+    let _raw_mode = RawMode::with_signals(&tty, false)?;
+    let mut editor = LineEditor::new();
+    // Row of the cursor below the start of the prompt.
+    let mut row = 0;
+    loop {
+        let (_, columns) = terminal_size();
+        let line = editor.line();
+        let highlighted = highlight(line);
+        let (output, cursor_row) =
+            redraw(prompt, &highlighted, line, editor.cursor(), row, columns);
+        write!(stdout, "{}", output)?;
+        stdout.flush()?;
+        row = cursor_row;
+
+        let edit = editor.handle_key(read_key(&mut tty)?);
+        if edit == Edit::Continue {
+            continue;
+        }
+        // Leave the cursor after the line, not in the middle of it.
+        let line = editor.line();
+        let (output, _) = redraw(prompt, &highlight(line), line, line.len(), row, columns);
+        write!(stdout, "{}", output)?;
+        // A line filling its last row already moved to the next one.
+        let end = match layout(&format!("{}{}", prompt, line), columns) {
+            (row, 0) if row > 0 => "",
+            _ => "\r\n",
+        };
+        match edit {
+            Edit::Done => {
+                write!(stdout, "{}", end)?;
+                return Ok(Some(editor.line().to_string()));
+            }
+            Edit::Cancel => {
+                write!(stdout, "^C\r\n")?;
+                return Err(ShellError::Interrupted);
+            }
+            // Ctrl-D on an empty line.
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// Keys edit the line at the cursor.
+#[test]
+fn handle_key_test() {
+    let mut editor = LineEditor::new();
+    for c in "ecoh".chars() {
+        editor.handle_key(Key::Char(c));
+    }
+    editor.handle_key(Key::Left);
+    editor.handle_key(Key::Backspace);
+    editor.handle_key(Key::Right);
+    editor.handle_key(Key::Char('o'));
+    assert_eq!(editor.line(), "echo");
+    editor.handle_key(Key::Home);
+    editor.handle_key(Key::Ctrl('d'));
+    assert_eq!((editor.line(), editor.cursor()), ("cho", 0));
+    editor.handle_key(Key::End);
+    editor.handle_key(Key::Char('é'));
+    editor.handle_key(Key::Left);
+    editor.handle_key(Key::Ctrl('u'));
+    assert_eq!((editor.line(), editor.cursor()), ("é", 0));
+    assert_eq!(editor.handle_key(Key::Enter), Edit::Done);
+    assert_eq!(editor.handle_key(Key::Ctrl('c')), Edit::Cancel);
+    assert_eq!(
+        LineEditor::new().handle_key(Key::Ctrl('d')),
+        Edit::EndOfInput
+    );
+}
+
+/// Command names are colored by whether they are known, wherever a
+/// command may start.
+#[test]
+fn highlight_test() {
+    let known = |name: &str| name == "echo" || name == "cat";
+    assert_eq!(
+        highlight("echo 'a b' | cta > out", known),
+        "\x1b[32mecho\x1b[0m \x1b[33m'a b'\x1b[0m \x1b[36m|\x1b[0m \x1b[31mcta\x1b[0m \
+         \x1b[35m>\x1b[0m out"
+    );
+    assert_eq!(
+        highlight("if cat a; then echo; fi", known),
+        "\x1b[1;34mif\x1b[0m \x1b[32mcat\x1b[0m a; \x1b[1;34mthen\x1b[0m \x1b[32mecho\x1b[0m; \
+         \x1b[1;34mfi\x1b[0m"
+    );
+    assert_eq!(
+        highlight("for x in a; do f; done", known),
+        "\x1b[1;34mfor\x1b[0m x \x1b[1;34min\x1b[0m a; \x1b[1;34mdo\x1b[0m \x1b[31mf\x1b[0m; \
+         \x1b[1;34mdone\x1b[0m"
+    );
+    assert_eq!(
+        highlight("x=1; f() { echo $x; } # done", known),
+        "x=1; \x1b[32mf\x1b[0m() \x1b[1;34m{\x1b[0m \x1b[32mecho\x1b[0m $x; \x1b[1;34m}\x1b[0m \
+         \x1b[90m# done\x1b[0m"
+    );
+}
+
+/// Colors take no room, wide characters two columns and text wraps at the
+/// width of the terminal.
+#[test]
+fn layout_test() {
+    assert_eq!(layout("\x1b[32mecho\x1b[0m a", 80), (0, 6));
+    assert_eq!(layout("日本語", 80), (0, 6));
+    assert_eq!(layout("e\u{301}", 80), (0, 1));
+    assert_eq!(layout("abcdefgh", 5), (1, 3));
+    // A full row moves on to the next one.
+    assert_eq!(layout("abcde", 5), (1, 0));
+    // A wide character not fitting in the last column goes on the next row.
+    assert_eq!(layout("abcd語", 5), (1, 2));
+}
+
+/// Redrawing goes back up to the prompt, then puts the cursor on its row
+/// and column.
+#[test]
+fn redraw_test() {
+    let (output, row) = redraw("> ", "echo", "echo", 4, 0, 80);
+    assert_eq!((output.as_str(), row), ("\r\x1b[J> echo\r\x1b[6C", 0));
+
+    // "> abcdefgh" on 4 columns: the cursor after "ab" starts the second row,
+    // the end of the line on the third.
+    let (output, row) = redraw("> ", "abcdefgh", "abcdefgh", 2, 2, 4);
+    assert_eq!(
+        (output.as_str(), row),
+        ("\x1b[2A\r\x1b[J> abcdefgh\x1b[1A\r", 1)
+    );
+    let (output, row) = redraw("> ", "ab", "ab", 2, 1, 4);
+    assert_eq!((output.as_str(), row), ("\x1b[1A\r\x1b[J> ab\r\n\r", 1));
+}
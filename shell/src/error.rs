@@ -1,26 +1,69 @@
-use std::io;
+use crate::command_output::{json_object, CommandOutput};
 use crate::shell_command::ShellCommand;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Byte range of the command line an error points at, e.g. the name of an
+/// unknown command. See `ShellError::underline`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
 
 /// Our own error type representing the different ways our
 /// shell can fail.
-/// Variants that take a String failed during parsing.
+/// Variants that take a String failed during parsing. Their `Span` is
+/// set by the parser, None for commands parsed as they run.
 /// Variants with a ShellCommand failed during evaluation.
 #[derive(Debug)]
 pub enum ShellError {
     /// An underlying io failure.
     IoError(io::Error),
+    /// An underlying io failure on a specific file.
+    PathError(PathBuf, io::Error),
+    /// Refused to recursively remove the root or home directory.
+    ProtectedPath(PathBuf),
+    /// A command working on several files failed for some of them, one
+    /// error for each. The other files were still processed.
+    MultipleErrors(Vec<ShellError>),
+    /// A command failed but still produced output, e.g. `tee` could not
+    /// write to some of its files. The output carries on down the pipe.
+    /// PartialFailure(output, error)
+    PartialFailure(CommandOutput, Box<ShellError>),
     /// A command is expected to be commands separated by pipes.
     /// but the command between some set of pipes was missing.
     /// e.g. cat file.txt | |
-    EmptyCommandInPipe(String),
+    EmptyCommandInPipe(String, Option<Span>),
     /// The command passed was recognized but the wrong number
     /// or argumetns were passed to it!
-    KnownCommandWrongArgs(String),
-    /// During parsing the command was not recognized.
-    UnknownCommand(String),
+    /// KnownCommandWrongArgs(command, usage, span)
+    KnownCommandWrongArgs(String, String, Option<Span>),
+    /// During parsing the command was not recognized, with the closest
+    /// known command if any. UnknownCommand(name, suggestion, span)
+    UnknownCommand(String, Option<String>, Option<Span>),
+    /// A `$(` or backtick is never closed in the command line.
+    UnclosedSubstitution(String, Option<Span>),
+    /// The command line ends before the word it still needs, e.g. `fi`.
+    /// The shell reads more lines to complete it.
+    UnexpectedEnd(String, Option<Span>),
+    /// A word or operator where it does not belong, e.g. `done` without
+    /// a loop.
+    UnexpectedToken(String, Option<Span>),
+    /// A job given to `fg`, `bg`, `wait` or `kill` does not exist, e.g. `%3`.
+    NoSuchJob(String),
+    /// The command was stopped by Ctrl-C, or `kill` for a job, before it
+    /// was done.
+    Interrupted,
 
     // Pipe commands, look at these later!
-
     /// Some command expected pipe input, but the previous command
     /// returned no input.
     ExpectedPipeInput(ShellCommand),
@@ -29,8 +72,12 @@ pub enum ShellError {
     UnexpectedPipeInput(ShellCommand),
     /// This command does not accept pipe input.
     PipeInputNotAccepted(ShellCommand),
-    /// Command produces no input for pipe.
+    /// Command produces no input for pipe, or for a file: `tail -f` only
+    /// prints to the terminal.
     NoInputForPipe(ShellCommand),
+    /// Pipe input for a text command is not valid UTF-8.
+    /// Passing --lossy to the command accepts it anyway.
+    InvalidUtf8(ShellCommand),
 }
 
 /// Allow for automatic conversion between io::Error to ShellError
@@ -40,3 +87,170 @@ impl From<io::Error> for ShellError {
         ShellError::IoError(error)
     }
 }
+
+impl ShellError {
+    /// Name of the variant, used as the stable `error` field when the
+    /// error is printed as JSON.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ShellError::IoError(_) => "IoError",
+            ShellError::PathError(_, _) => "PathError",
+            ShellError::ProtectedPath(_) => "ProtectedPath",
+            ShellError::MultipleErrors(_) => "MultipleErrors",
+            ShellError::PartialFailure(_, _) => "PartialFailure",
+            ShellError::EmptyCommandInPipe(..) => "EmptyCommandInPipe",
+            ShellError::KnownCommandWrongArgs(..) => "KnownCommandWrongArgs",
+            ShellError::UnknownCommand(..) => "UnknownCommand",
+            ShellError::UnclosedSubstitution(..) => "UnclosedSubstitution",
+            ShellError::UnexpectedEnd(..) => "UnexpectedEnd",
+            ShellError::UnexpectedToken(..) => "UnexpectedToken",
+            ShellError::NoSuchJob(_) => "NoSuchJob",
+            ShellError::Interrupted => "Interrupted",
+            ShellError::ExpectedPipeInput(_) => "ExpectedPipeInput",
+            ShellError::UnexpectedPipeInput(_) => "UnexpectedPipeInput",
+            ShellError::PipeInputNotAccepted(_) => "PipeInputNotAccepted",
+            ShellError::NoInputForPipe(_) => "NoInputForPipe",
+            ShellError::InvalidUtf8(_) => "InvalidUtf8",
+        }
+    }
+
+    /// Exit status of a command failing with this error, for scripts to
+    /// check: 127 for an unknown command, 2 for a command line that does
+    /// not parse, 130 for a command stopped by Ctrl-C like other shells
+    /// and 1 for a command that failed. Several errors give the highest
+    /// of their statuses.
+    pub fn status(&self) -> i32 {
+        match self {
+            ShellError::IoError(_) => 1,
+            ShellError::PathError(_, _) => 1,
+            ShellError::ProtectedPath(_) => 1,
+            ShellError::MultipleErrors(errors) => {
+                errors.iter().map(ShellError::status).max().unwrap_or(1)
+            }
+            ShellError::PartialFailure(_, e) => e.status(),
+            ShellError::EmptyCommandInPipe(..) => 2,
+            ShellError::KnownCommandWrongArgs(..) => 2,
+            ShellError::UnknownCommand(..) => 127,
+            ShellError::UnclosedSubstitution(..) => 2,
+            ShellError::UnexpectedEnd(..) => 2,
+            ShellError::UnexpectedToken(..) => 2,
+            ShellError::NoSuchJob(_) => 1,
+            ShellError::Interrupted => 130,
+            ShellError::ExpectedPipeInput(_) => 1,
+            ShellError::UnexpectedPipeInput(_) => 1,
+            ShellError::PipeInputNotAccepted(_) => 1,
+            ShellError::NoInputForPipe(_) => 1,
+            ShellError::InvalidUtf8(_) => 1,
+        }
+    }
+
+    /// Part of the command line this error points at, if it failed to
+    /// parse.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ShellError::EmptyCommandInPipe(_, span)
+            | ShellError::KnownCommandWrongArgs(_, _, span)
+            | ShellError::UnknownCommand(_, _, span)
+            | ShellError::UnclosedSubstitution(_, span)
+            | ShellError::UnexpectedEnd(_, span)
+            | ShellError::UnexpectedToken(_, span) => *span,
+            _ => None,
+        }
+    }
+
+    /// This error pointing at `span` of the command line, for errors that
+    /// failed to parse. Other errors are returned as they are.
+    pub fn with_span(mut self, span: Span) -> Self {
+        match &mut self {
+            ShellError::EmptyCommandInPipe(_, s)
+            | ShellError::KnownCommandWrongArgs(_, _, s)
+            | ShellError::UnknownCommand(_, _, s)
+            | ShellError::UnclosedSubstitution(_, s)
+            | ShellError::UnexpectedEnd(_, s)
+            | ShellError::UnexpectedToken(_, s) => *s = Some(span),
+            _ => {}
+        }
+        self
+    }
+
+    /// The line of `source` this error points at, followed by a line
+    /// underlining its span with carets, e.g.
+    ///
+    /// ```text
+    /// cat a | fnd-string x
+    ///         ^^^^^^^^^^
+    /// ```
+    ///
+    /// None if the error has no span.
+    pub fn underline(&self, source: &str) -> Option<String> {
+        let span = self.span()?;
+        let start = span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        // Tabs are kept so the carets line up with the line above.
+        let indent: String = source[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let end = span.end.clamp(start, line_end);
+        let width = source[start..end].chars().count().max(1);
+        Some(format!(
+            "{}\n{}{}",
+            &source[line_start..line_end],
+            indent,
+            "^".repeat(width)
+        ))
+    }
+
+    /// Single line JSON object with the `error` name and a human readable
+    /// `message`.
+    pub fn to_json(&self) -> String {
+        json_object(
+            &["error".to_string(), "message".to_string()],
+            &[self.name().to_string(), self.to_string()],
+        )
+    }
+}
+
+impl fmt::Display for ShellError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShellError::IoError(e) => write!(f, "{}", e),
+            ShellError::PathError(path, e) => write!(f, "{}: {}", path.display(), e),
+            ShellError::ProtectedPath(path) => write!(
+                f,
+                "refusing to remove {} recursively, use --no-preserve-root to override",
+                path.display()
+            ),
+            ShellError::MultipleErrors(errors) => {
+                let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", errors.join("\n"))
+            }
+            ShellError::PartialFailure(_, e) => write!(f, "{}", e),
+            ShellError::EmptyCommandInPipe(c, _) => write!(f, "empty command in pipe: {}", c),
+            ShellError::KnownCommandWrongArgs(c, usage, _) => {
+                write!(f, "wrong arguments: {}\nusage: {}", c, usage)
+            }
+            ShellError::UnknownCommand(c, None, _) => write!(f, "unknown command: {}", c),
+            ShellError::UnknownCommand(c, Some(suggestion), _) => {
+                write!(f, "unknown command: {}, did you mean {}?", c, suggestion)
+            }
+            ShellError::UnclosedSubstitution(c, _) => write!(f, "unclosed substitution: {}", c),
+            ShellError::UnexpectedEnd(word, _) => write!(f, "unexpected end, expected {}", word),
+            ShellError::UnexpectedToken(word, _) => write!(f, "unexpected {}", word),
+            ShellError::NoSuchJob(job) => write!(f, "no such job: {}", job),
+            ShellError::Interrupted => write!(f, "interrupted"),
+            ShellError::ExpectedPipeInput(c) => write!(f, "expected pipe input: {:?}", c),
+            ShellError::UnexpectedPipeInput(c) => write!(f, "unexpected pipe input: {:?}", c),
+            ShellError::PipeInputNotAccepted(c) => {
+                write!(f, "pipe input not accepted: {:?}", c)
+            }
+            ShellError::NoInputForPipe(c) => write!(f, "no input for pipe: {:?}", c),
+            ShellError::InvalidUtf8(c) => {
+                write!(f, "pipe input is not valid UTF-8, try --lossy: {:?}", c)
+            }
+        }
+    }
+}
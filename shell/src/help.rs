@@ -0,0 +1,126 @@
+//! `help`, `type` and `which`: what the user can learn about the commands
+//! of a registry.
+
+use std::path::PathBuf;
+
+use crate::command::Registry;
+use crate::error::ShellError;
+use crate::CommandOutput;
+
+/// Lists every command of `registry` with its summary, or describes the
+/// `command` in detail: usage, summary and flags.
+pub fn help(registry: &Registry, command: Option<&str>) -> Result<CommandOutput, ShellError> {
+    let command = match command {
+        Some(command) => command,
+        None => {
+            let rows = registry
+                .commands()
+                .map(|info| vec![info.name.to_string(), info.summary.to_string()])
+                .collect();
+            let columns = vec!["command".to_string(), "summary".to_string()];
+            return Ok(CommandOutput::Table(columns, rows));
+        }
+    };
+
+    let info = registry
+        .find(command)
+        .ok_or_else(|| registry.unknown(command))?;
+    let mut lines = vec![format!("usage: {}", info.usage()), info.summary.to_string()];
+    if !info.aliases.is_empty() {
+        lines.push(format!("aliases: {}", info.aliases.join(", ")));
+    }
+    for (flag, description) in info.flags {
+        lines.push(format!("  {:<20}{}", flag, description));
+    }
+    Ok(CommandOutput::Single(lines.join("\n")))
+}
+
+/// First executable file called `name` in the directories of `$PATH`.
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| {
+            candidate
+                .metadata()
+                .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+                .unwrap_or(false)
+        })
+}
+
+/// What a command `name` refers to: a built-in, an alias of one, or a
+/// program found in `$PATH`, which this shell does not run.
+/// Returns a table with columns `name`, `kind` and `target`.
+/// Fails if a name is none of these.
+pub fn type_of(registry: &Registry, names: &[String]) -> Result<CommandOutput, ShellError> {
+    let mut rows = vec![];
+    for name in names {
+        let (kind, target) = match registry.find(name) {
+            Some(info) if info.name == name => ("built-in", info.name.to_string()),
+            Some(info) => ("alias", info.name.to_string()),
+            None => match find_in_path(name) {
+                Some(path) => ("program", path.display().to_string()),
+                None => return Err(registry.unknown(name)),
+            },
+        };
+        rows.push(vec![name.to_string(), kind.to_string(), target]);
+    }
+    let columns = ["name", "kind", "target"];
+    Ok(CommandOutput::Table(
+        columns.iter().map(|c| c.to_string()).collect(),
+        rows,
+    ))
+}
+
+/// "which"-like function: what runs for each name, one line per name.
+/// Programs are shown by their path.
+pub fn which(registry: &Registry, names: &[String]) -> Result<CommandOutput, ShellError> {
+    let lines = match type_of(registry, names)? {
+        CommandOutput::Table(_, rows) => rows
+            .iter()
+            .map(|row| match row[1].as_str() {
+                "built-in" => format!("{}: shell built-in", row[0]),
+                "alias" => format!("{}: aliased to {}", row[0], row[2]),
+                _ => row[2].clone(),
+            })
+            .collect(),
+        _ => vec![],
+    };
+    Ok(CommandOutput::List(lines))
+}
+
+/// Every built-in is found by its name and its aliases.
+#[test]
+fn find_builtin_test() {
+    let registry = Registry::with_builtins();
+    for info in registry.commands() {
+        assert_eq!(registry.find(info.name).unwrap().name, info.name);
+    }
+    assert_eq!(registry.find("hd").unwrap().name, "hexdump");
+    assert!(registry.find("nope").is_none());
+    assert_eq!(
+        registry.find("head").unwrap().usage(),
+        "head [-n count] [files...]"
+    );
+}
+
+/// Built-ins, aliases and programs are told apart.
+#[test]
+fn type_test() {
+    let registry = Registry::with_builtins();
+    let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+    match type_of(&registry, &names(&["ls", "hd", "sh"])).unwrap() {
+        CommandOutput::Table(_, rows) => {
+            let kinds: Vec<&str> = rows.iter().map(|row| row[1].as_str()).collect();
+            assert_eq!(kinds, vec!["built-in", "alias", "program"]);
+        }
+        output => panic!("expected a table, got {:?}", output),
+    }
+    assert!(type_of(&registry, &names(&["no-such-command"])).is_err());
+    assert_eq!(
+        which(&registry, &names(&["hd"])).unwrap().to_string(),
+        Some("hd: aliased to hexdump".to_string())
+    );
+}
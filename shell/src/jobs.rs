@@ -0,0 +1,346 @@
+//! Command lines running in the background, and how the shell interrupts
+//! or pauses a command line while it runs.
+//!
+//! Jobs run on their own thread with a copy of the session, commands are
+//! never killed but stop at the next `CancellationToken::checkpoint`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::error::ShellError;
+use crate::session::Session;
+use crate::shell_command::ShellCommand;
+use crate::CommandOutput;
+
+/// Set by the SIGINT handler, see `catch_signals`.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+/// Set by the SIGTSTP handler, see `catch_signals`.
+static SUSPENDED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    match signal {
+        libc::SIGINT => INTERRUPTED.store(true, Ordering::SeqCst),
+        _ => SUSPENDED.store(true, Ordering::SeqCst),
+    }
+}
+
+/// Catches Ctrl-C and Ctrl-Z instead of letting them kill or stop the
+/// process. Ctrl-C then cancels the command line running in the
+/// foreground, Ctrl-Z pauses the job brought to the foreground by `fg`.
+pub fn catch_signals() {
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTSTP, handler);
+    }
+}
+
+/// Forgets Ctrl-C and Ctrl-Z pressed before a command line started, e.g.
+/// at the prompt.
+pub fn clear_signals() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+    SUSPENDED.store(false, Ordering::SeqCst);
+}
+
+/// Whether Ctrl-C was pressed since the last call.
+fn take_interrupt() -> bool {
+    INTERRUPTED.swap(false, Ordering::SeqCst)
+}
+
+/// Whether Ctrl-Z was pressed since the last call.
+fn take_suspend() -> bool {
+    SUSPENDED.swap(false, Ordering::SeqCst)
+}
+
+#[derive(Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    paused: Mutex<bool>,
+    resumed: Condvar,
+}
+
+/// Asks a running command line to stop or pause. Long-running commands
+/// call `checkpoint` between steps of their work.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    state: Arc<TokenState>,
+    /// Whether Ctrl-C cancels the command line, true for the foreground.
+    interruptible: bool,
+}
+
+impl CancellationToken {
+    /// Token for command lines run in the foreground, cancelled by Ctrl-C
+    /// once `catch_signals` was called.
+    pub fn foreground() -> Self {
+        CancellationToken {
+            state: Arc::default(),
+            interruptible: true,
+        }
+    }
+
+    /// Token for a background job, only cancelled through `cancel`.
+    pub fn background() -> Self {
+        CancellationToken::default()
+    }
+
+    /// Stops the command line at its next checkpoint, even if paused.
+    pub fn cancel(&self) {
+        // Under the lock, so a checkpoint cannot miss it between checking
+        // and starting to wait.
+        let _paused = self.state.paused.lock().unwrap();
+        self.state.cancelled.store(true, Ordering::SeqCst);
+        self.state.resumed.notify_all();
+    }
+
+    /// Whether the command line was asked to stop.
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::SeqCst)
+            || self.interruptible && INTERRUPTED.load(Ordering::SeqCst)
+    }
+
+    /// Keeps the command line waiting at its next checkpoint.
+    pub fn pause(&self) {
+        *self.state.paused.lock().unwrap() = true;
+    }
+
+    /// Lets a paused command line carry on.
+    pub fn resume(&self) {
+        *self.state.paused.lock().unwrap() = false;
+        self.state.resumed.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.state.paused.lock().unwrap()
+    }
+
+    /// Waits while the command line is paused, then fails with
+    /// `ShellError::Interrupted` if it was asked to stop.
+    pub fn checkpoint(&self) -> Result<(), ShellError> {
+        let mut paused = self.state.paused.lock().unwrap();
+        while *paused && !self.is_cancelled() {
+            paused = self.state.resumed.wait(paused).unwrap();
+        }
+        if self.is_cancelled() {
+            return Err(ShellError::Interrupted);
+        }
+        Ok(())
+    }
+}
+
+/// Result of a finished job and its status.
+pub type JobResult = (Result<CommandOutput, ShellError>, i32);
+
+/// What a job is doing, as shown by `jobs`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Stopped,
+    Done,
+}
+
+impl JobState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobState::Running => "Running",
+            JobState::Stopped => "Stopped",
+            JobState::Done => "Done",
+        }
+    }
+}
+
+/// A command line running in the background.
+pub struct Job {
+    /// Number the user refers to the job by, as `%id`.
+    pub id: usize,
+    /// The command line, without the `&`.
+    pub line: String,
+    token: CancellationToken,
+    handle: JoinHandle<JobResult>,
+}
+
+impl Job {
+    pub fn state(&self) -> JobState {
+        if self.handle.is_finished() {
+            JobState::Done
+        } else if self.token.is_paused() {
+            JobState::Stopped
+        } else {
+            JobState::Running
+        }
+    }
+
+    /// Stops the job at its next checkpoint.
+    pub fn kill(&self) {
+        self.token.cancel();
+    }
+
+    /// Pauses the job at its next checkpoint.
+    pub fn stop(&self) {
+        self.token.pause();
+    }
+
+    /// Lets a stopped job carry on in the background.
+    pub fn resume(&self) {
+        self.token.resume();
+    }
+
+    /// Waits for the job in the foreground. Ctrl-C kills it and Ctrl-Z
+    /// stops it, the job is then given back while it keeps its thread.
+    pub fn wait(self) -> Result<JobResult, Job> {
+        while !self.handle.is_finished() {
+            if take_interrupt() {
+                self.kill();
+            }
+            if take_suspend() {
+                self.stop();
+                return Err(self);
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        Ok(self.finish())
+    }
+
+    /// Result of a job in the `Done` state.
+    fn finish(self) -> JobResult {
+        self.handle.join().unwrap_or_else(|_| {
+            let error = std::io::Error::other("job panicked");
+            (Err(ShellError::IoError(error)), 1)
+        })
+    }
+}
+
+/// The jobs of a session, by id.
+#[derive(Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+}
+
+impl Jobs {
+    /// Runs `command` on its own thread with `session`, a copy of the
+    /// session starting the job. Returns the id of the job.
+    pub fn spawn(&mut self, line: &str, command: ShellCommand, mut session: Session) -> usize {
+        let id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        let token = CancellationToken::background();
+        session.set_token(token.clone());
+        let handle = thread::spawn(move || {
+            let result = session.execute(&command);
+            (result, session.status())
+        });
+        self.jobs.push(Job {
+            id,
+            line: line.to_string(),
+            token,
+            handle,
+        });
+        id
+    }
+
+    /// Every job, oldest first.
+    pub fn list(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    /// The job `id`, the latest one if None.
+    pub fn get(&self, id: Option<usize>) -> Result<&Job, ShellError> {
+        let job = match id {
+            Some(id) => self.jobs.iter().find(|job| job.id == id),
+            None => self.jobs.last(),
+        };
+        job.ok_or_else(|| ShellError::NoSuchJob(id_spec(id)))
+    }
+
+    /// Removes the job `id`, the latest one if None, to wait for it.
+    pub fn take(&mut self, id: Option<usize>) -> Result<Job, ShellError> {
+        let index = match id {
+            Some(id) => self.jobs.iter().position(|job| job.id == id),
+            None => self.jobs.len().checked_sub(1),
+        };
+        match index {
+            Some(index) => Ok(self.jobs.remove(index)),
+            None => Err(ShellError::NoSuchJob(id_spec(id))),
+        }
+    }
+
+    /// Waits until the job `id`, or every job if None, is done. Stopped
+    /// jobs are not waited for. Ctrl-C stops waiting, the jobs carry on.
+    pub fn wait(&self, id: Option<usize>) -> Result<(), ShellError> {
+        let jobs = match id {
+            Some(_) => vec![self.get(id)?],
+            None => self.jobs.iter().collect(),
+        };
+        while jobs.iter().any(|job| job.state() == JobState::Running) && !take_interrupt() {
+            thread::sleep(Duration::from_millis(20));
+        }
+        Ok(())
+    }
+
+    /// Gives back a job taken with `take` that is still running.
+    pub fn insert(&mut self, job: Job) {
+        let index = self.jobs.partition_point(|other| other.id < job.id);
+        self.jobs.insert(index, job);
+    }
+
+    /// Removes the jobs that are done, for the shell to report them: their
+    /// id, command line and result.
+    pub fn finished(&mut self) -> Vec<(usize, String, JobResult)> {
+        let (done, running) = self
+            .jobs
+            .drain(..)
+            .partition(|job| job.state() == JobState::Done);
+        self.jobs = running;
+        done.into_iter()
+            .map(|job: Job| (job.id, job.line.clone(), job.finish()))
+            .collect()
+    }
+}
+
+/// How the user refers to job `id`, for errors.
+fn id_spec(id: Option<usize>) -> String {
+    match id {
+        Some(id) => format!("%{}", id),
+        None => "current job".to_string(),
+    }
+}
+
+/// Parses a job given as `%n`, as `fg`, `bg`, `wait` and `kill` take them.
+pub fn parse_job_id(arg: &str) -> Option<usize> {
+    arg.strip_prefix('%')?.parse().ok()
+}
+
+/// A paused command line waits at its checkpoint until resumed or
+/// cancelled.
+#[test]
+fn checkpoint_test() {
+    let token = CancellationToken::background();
+    assert!(token.checkpoint().is_ok());
+    token.pause();
+    let waiting = token.clone();
+    let handle = thread::spawn(move || waiting.checkpoint());
+    thread::sleep(Duration::from_millis(50));
+    assert!(!handle.is_finished());
+    token.cancel();
+    assert!(matches!(
+        handle.join().unwrap(),
+        Err(ShellError::Interrupted)
+    ));
+    assert!(matches!(token.checkpoint(), Err(ShellError::Interrupted)));
+}
+
+/// A cancel landing just as a paused checkpoint starts waiting still
+/// wakes it up.
+#[test]
+fn cancel_paused_test() {
+    for _ in 0..2000 {
+        let token = CancellationToken::background();
+        token.pause();
+        let waiting = token.clone();
+        let (done, finished) = std::sync::mpsc::channel();
+        let handle = thread::spawn(move || done.send(waiting.checkpoint()).unwrap());
+        token.cancel();
+        let result = finished.recv_timeout(Duration::from_secs(5));
+        assert!(matches!(result, Ok(Err(ShellError::Interrupted))));
+        handle.join().unwrap();
+    }
+}
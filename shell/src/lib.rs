@@ -0,0 +1,33 @@
+//! Shell which accepts common shell commands, as a library. Commands are
+//! done in process using IO, nothing is "forked" to an underlying shell.
+//!
+//! A `Session` runs command lines and returns their output:
+//!
+//! ```
+//! let mut session = shell::Session::new();
+//! let output = session.run("echo -n hi | tr a-z A-Z").unwrap();
+//! assert_eq!(output.to_string(), Some("HI".to_string()));
+//! ```
+//!
+//! New commands implement `command::Command` and are added to the
+//! session's registry.
+
+pub mod builtins;
+pub mod checksum;
+pub mod command;
+pub mod command_output;
+pub mod commands;
+pub mod editor;
+pub mod error;
+pub mod help;
+pub mod jobs;
+pub mod pager;
+pub mod parser;
+pub mod prompt;
+pub mod session;
+pub mod shell_command;
+
+pub use crate::command_output::{CommandOutput, OutputFormat};
+pub use crate::error::ShellError;
+pub use crate::session::Session;
+pub use crate::shell_command::ShellCommand;
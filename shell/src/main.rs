@@ -1,37 +1,213 @@
 //! Command line shell which accepts common shell commands.
-//! Easily extendible to do more commands.
-//! Does not "fork" work to underlying shell. Does all commands
-//! using IO.
+//! Easily extendible to do more commands, see the `shell` library this
+//! loop runs on.
 
 use std::io;
 use std::io::prelude::*;
+use std::io::IsTerminal;
+use std::sync::Arc;
 
-mod command_output;
-mod commands;
-mod error;
-mod shell_command;
-
-use crate::command_output::*;
-use shell_command::ShellCommand;
+use shell::command::Presentation;
+use shell::{editor, jobs, pager, prompt, CommandOutput, OutputFormat, Session, ShellError};
 
 /// Main read eval loop for shell.
 /// Keeps accepting commands, parses them into a ShellCommand,
 /// and executes the command. Either prints CommandOutput, or prints
 /// error.
+/// Passing `--json` or `--csv` to the shell sets the output format of the session,
+/// `--dry-run` and `--trash` start it in dry-run or trash mode.
+/// The shell exits with the status of the last command line, or the one
+/// given to `exit`, or at the end of input, e.g. Ctrl-D. Ctrl-C stops the
+/// command line running with `Interrupted` instead of the shell.
+/// Lines ending in the middle of an `if`, a loop or a function are read
+/// up to its end before it runs.
+/// The prompt is set by the `PROMPT` variable, taken from the environment
+/// at start, see `prompt::render`. On a terminal the line is highlighted
+/// as it is typed.
 fn main() {
+    jobs::catch_signals();
+    let mut session = Session::new();
+    let settings = &mut session.settings;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--dry-run" => settings.change_mode.dry_run = true,
+            "--trash" => settings.change_mode.trash = true,
+            arg => {
+                if let Some(format) = OutputFormat::from_flag(arg) {
+                    settings.output_format = format;
+                }
+            }
+        }
+    }
+
+    if let Ok(prompt) = std::env::var("PROMPT") {
+        session.set_var("PROMPT", prompt);
+    }
+
     let stdin = io::stdin();
-    print!("> ");
-    io::stdout().flush().ok().expect("Could not flush stdout");
+    let interactive = stdin.is_terminal() && pager::is_terminal();
+    let mut lines = stdin.lock().lines();
+    let mut script = String::new();
+    let mut format = None;
+    loop {
+        let prompt = match script.is_empty() {
+            true => prompt::render(&session),
+            false => "... ".to_string(),
+        };
+        let line = if interactive {
+            let registry = session.registry();
+            let known =
+                |name: &str| registry.find(name).is_some() || session.function(name).is_some();
+            match editor::read_line(&prompt, |line| editor::highlight(line, known)) {
+                Ok(Some(line)) => line,
+                Ok(None) => break,
+                // Ctrl-C drops the line, and the lines before it if unfinished.
+                Err(ShellError::Interrupted) => {
+                    script.clear();
+                    format = None;
+                    continue;
+                }
+                Err(e) => {
+                    print_error("Error", &e, session.settings.output_format);
+                    break;
+                }
+            }
+        } else {
+            print!("{}", prompt);
+            io::stdout().flush().expect("Could not flush stdout");
+            match lines.next() {
+                Some(line) => line.unwrap(),
+                None => break,
+            }
+        };
+
+        // A --json or --csv flag ending the line only applies to this line.
+        let (user_input, line_format) = OutputFormat::take_flag(&line);
+        format = format.or(line_format);
+        if !script.is_empty() {
+            script.push('\n');
+        }
+        script.push_str(&user_input);
+        let command = session.parse(&script);
+        if let Err(ShellError::UnexpectedEnd(..)) = command {
+            continue;
+        }
+        let source = std::mem::take(&mut script);
 
-    for line in stdin.lock().lines() {
-        let user_input = line.unwrap();
+        let presentation = match &command {
+            Ok(command) if format.is_none() && pager::is_terminal() => command.presentation(),
+            _ => Presentation::Plain,
+        };
+        // Statements of a script print their output as they end.
+        let line_format = format;
+        session.set_output(Some(Arc::new(move |result, format| {
+            print_result(result, line_format.unwrap_or(format), &Presentation::Plain)
+        })));
+        let result = command.map(|command| {
+            jobs::clear_signals();
+            session.execute(&command)
+        });
+        let format = format.take().unwrap_or(session.settings.output_format);
+
+        match result {
+            Ok(result) => print_result(result, format, &presentation),
+            Err(e) => {
+                session.set_status(e.status());
+                print_parse_error(&source, &e, format);
+            }
+        }
+        if let Some(status) = session.exit_status() {
+            std::process::exit(status);
+        }
+
+        report_jobs(&mut session);
+    }
+    // Ctrl-D at the prompt, end the line it was pressed on.
+    if pager::is_terminal() {
+        println!();
+    }
+    // Input ended in the middle of a compound command.
+    if let Err(e) = session.parse(&script) {
+        session.set_status(e.status());
+        let format = format.unwrap_or(session.settings.output_format);
+        print_parse_error(&script, &e, format);
+    }
+    std::process::exit(session.status());
+}
 
-        // Step #20 ShellCommand functions and CommandOutput
-        // function to parse the user input, execute the command.
-        // and print it!
-        unimplemented!();
+/// Prints the output of a command, or the error it failed with.
+fn print_result(
+    result: Result<CommandOutput, ShellError>,
+    format: OutputFormat,
+    presentation: &Presentation,
+) {
+    match result {
+        Ok(output) => print_output(&output, format, presentation),
+        // Output that made it through the pipe is still worth printing.
+        Err(ShellError::PartialFailure(output, e)) => {
+            print_output(&output, format, presentation);
+            print_error("Error", &e, format);
+        }
+        Err(e) => print_error("Error", &e, format),
+    }
+}
+
+/// Prints the result of every job done since the last prompt, followed by
+/// a line telling how it ended.
+fn report_jobs(session: &mut Session) {
+    let format = session.settings.output_format;
+    for (id, line, (result, status)) in session.jobs_mut().finished() {
+        print_result(result, format, &Presentation::Plain);
+        let state = match status {
+            0 => "Done".to_string(),
+            status => format!("Exit {}", status),
+        };
+        CommandOutput::Single(format!("[{}] {} {}", id, state, line)).print_as(format);
+    }
+}
+
+/// Prints the output of a command in `format`. Text output is shown the
+/// way the command prefers on a terminal, see `Presentation`.
+fn print_output(output: &CommandOutput, format: OutputFormat, presentation: &Presentation) {
+    match (format, presentation) {
+        (OutputFormat::Text, Presentation::Paged(title)) => {
+            if let Err(e) = pager::page(title, output.lines()) {
+                print_error("Error", &e, format);
+            }
+        }
+        (OutputFormat::Text, Presentation::Colored(color)) => {
+            for line in output.lines() {
+                println!("{}", color(&line));
+            }
+        }
+        _ => output.print_as(format),
+    }
+}
+
+/// Prints an error from either parsing or executing a command.
+/// In JSON mode errors are printed as JSON objects so tools reading our
+/// output never have to deal with free form text.
+/// Commands failing on several files print one error per file.
+fn print_error(context: &str, error: &ShellError, format: OutputFormat) {
+    if let ShellError::MultipleErrors(errors) = error {
+        for error in errors {
+            print_error(context, error, format);
+        }
+    } else if format == OutputFormat::Json {
+        println!("{}", error.to_json());
+    } else {
+        println!("{}: {}", context, error);
+    }
+}
 
-        print!("> ");
-        io::stdout().flush().ok().expect("Could not flush stdout");
+/// Prints an error from parsing `source`. In text mode the line it comes
+/// from is shown with the part at fault underlined.
+fn print_parse_error(source: &str, error: &ShellError, format: OutputFormat) {
+    match error.underline(source) {
+        Some(underline) if format == OutputFormat::Text => {
+            println!("Unable to parse command: {}", error);
+            println!("{}", underline);
+        }
+        _ => print_error("Unable to parse command", error, format),
     }
 }
@@ -0,0 +1,421 @@
+//! Interactive pager used by `more` when printing to a terminal.
+//! Keys are read straight from the terminal (/dev/tty) in raw mode, so
+//! this only works on unix-like systems.
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::IsTerminal;
+use std::os::unix::io::AsRawFd;
+
+use crate::commands::line_matches;
+use crate::error::ShellError;
+
+/// A key press understood by the pager, or the line editor of the prompt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    /// A letter typed with Ctrl held, e.g. `Ctrl('c')`.
+    Ctrl(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Backspace,
+    Escape,
+}
+
+/// What the terminal loop should do after a key was handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Continue,
+    /// Read a search pattern from the user, then call `Pager::search`.
+    Search,
+    Quit,
+}
+
+/// Scrolling and searching state of the pager. Knows nothing about the
+/// terminal so it can be tested on its own.
+pub struct Pager {
+    lines: Vec<String>,
+    /// Index of the first line on screen.
+    top: usize,
+    /// Number of lines of content on screen.
+    height: usize,
+    line_numbers: bool,
+    pattern: Option<String>,
+    /// Line of the match we last jumped to. `n` and `N` search from here.
+    last_match: Option<usize>,
+    /// Shown in the status line instead of the position, until the next key.
+    message: Option<String>,
+}
+
+impl Pager {
+    pub fn new(lines: Vec<String>, height: usize) -> Pager {
+        Pager {
+            lines,
+            top: 0,
+            height: height.max(1),
+            line_numbers: false,
+            pattern: None,
+            last_match: None,
+            message: None,
+        }
+    }
+
+    pub fn resize(&mut self, height: usize) {
+        self.height = height.max(1);
+        self.top = self.top.min(self.max_top());
+    }
+
+    /// The furthest we can scroll while still filling the screen.
+    fn max_top(&self) -> usize {
+        self.lines.len().saturating_sub(self.height)
+    }
+
+    fn scroll_to(&mut self, top: usize) {
+        self.top = top.min(self.max_top());
+        self.last_match = None;
+    }
+
+    /// Updates the pager for `key`.
+    /// `space`/`f` and `b` move by pages, `j`/`Enter` and `k` by lines,
+    /// `g`/`G` jump to the start/end, `/` starts a search, `n`/`N` go to
+    /// the next/previous match, `l` toggles line numbers and `q` quits.
+    pub fn handle_key(&mut self, key: Key) -> Action {
+        self.message = None;
+        match key {
+            Key::Char(' ') | Key::Char('f') | Key::PageDown => {
+                self.scroll_to(self.top + self.height)
+            }
+            Key::Char('b') | Key::PageUp => self.scroll_to(self.top.saturating_sub(self.height)),
+            Key::Char('j') | Key::Enter | Key::Down => self.scroll_to(self.top + 1),
+            Key::Char('k') | Key::Up => self.scroll_to(self.top.saturating_sub(1)),
+            Key::Char('g') | Key::Home => self.scroll_to(0),
+            Key::Char('G') | Key::End => self.scroll_to(self.max_top()),
+            Key::Char('l') => self.line_numbers = !self.line_numbers,
+            Key::Char('n') => self.next_match(),
+            Key::Char('N') => self.previous_match(),
+            Key::Char('/') => return Action::Search,
+            Key::Char('q') | Key::Escape => return Action::Quit,
+            _ => {}
+        }
+        Action::Continue
+    }
+
+    /// Searches forward for `pattern` from the top of the screen.
+    /// An empty pattern repeats the last search.
+    pub fn search(&mut self, pattern: String) {
+        if !pattern.is_empty() {
+            self.pattern = Some(pattern);
+        }
+        self.last_match = None;
+        self.next_match();
+    }
+
+    fn next_match(&mut self) {
+        let start = self.last_match.map(|m| m + 1).unwrap_or(self.top);
+        let found = self.find_match(start..self.lines.len());
+        self.jump_to_match(found);
+    }
+
+    fn previous_match(&mut self) {
+        let end = self.last_match.unwrap_or(self.top);
+        let found = self.find_match((0..end).rev());
+        self.jump_to_match(found);
+    }
+
+    fn find_match(&self, mut range: impl Iterator<Item = usize>) -> Option<usize> {
+        let pattern = self.pattern.as_ref()?;
+        range.find(|&i| line_matches(&self.lines[i], pattern))
+    }
+
+    fn jump_to_match(&mut self, found: Option<usize>) {
+        match (found, &self.pattern) {
+            (Some(i), _) => {
+                self.top = i.min(self.max_top());
+                self.last_match = Some(i);
+            }
+            (None, Some(pattern)) => self.message = Some(format!("Pattern not found: {}", pattern)),
+            (None, None) => self.message = Some("No previous search".to_string()),
+        }
+    }
+
+    /// Lines to draw for a terminal `width` columns wide: one screen of
+    /// content followed by the status line.
+    pub fn screen(&self, title: &str, width: usize) -> Vec<String> {
+        let end = (self.top + self.height).min(self.lines.len());
+        let mut screen: Vec<String> = (self.top..end)
+            .map(|i| {
+                let number = if self.line_numbers {
+                    format!("{:>6} ", i + 1)
+                } else {
+                    String::new()
+                };
+                let line: String = self.lines[i]
+                    .chars()
+                    .take(width.saturating_sub(number.len()))
+                    .collect();
+                number + &self.highlight(line)
+            })
+            .collect();
+        screen.resize(self.height, "~".to_string());
+
+        let status = match &self.message {
+            Some(message) => message.clone(),
+            None => format!(
+                "{} lines {}-{}/{} ({}%)",
+                title,
+                self.top + 1,
+                end,
+                self.lines.len(),
+                end * 100 / self.lines.len().max(1)
+            ),
+        };
+        let status: String = status.chars().take(width).collect();
+        screen.push(format!("\x1b[7m{}\x1b[0m", status));
+        screen
+    }
+
+    /// Shows matches of the current search in reverse video.
+    fn highlight(&self, line: String) -> String {
+        match &self.pattern {
+            Some(pattern) if line_matches(&line, pattern) => {
+                line.replace(pattern.as_str(), &format!("\x1b[7m{}\x1b[27m", pattern))
+            }
+            _ => line,
+        }
+    }
+}
+
+/// Whether output is going to a terminal, i.e. paging makes sense.
+pub fn is_terminal() -> bool {
+    io::stdout().is_terminal()
+}
+
+/// Shows `lines` one screen at a time, titled `title` in the status line.
+/// Output fitting on one screen is simply printed.
+pub fn page(title: &str, lines: Vec<String>) -> Result<(), ShellError> {
+    let (rows, _) = terminal_size();
+    if lines.len() < rows {
+        for line in lines {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
+    let mut tty = File::open("/dev/tty")?;
+    let _raw_mode = RawMode::enable(&tty)?;
+    let mut stdout = io::stdout();
+    // Use the alternate screen so the shell's scrollback is left alone.
+    write!(stdout, "\x1b[?1049h")?;
+
+    let mut pager = Pager::new(lines, rows - 1);
+    let result = (|| -> Result<(), ShellError> {
+        loop {
+            let (rows, cols) = terminal_size();
+            pager.resize(rows - 1);
+            write!(
+                stdout,
+                "\x1b[H\x1b[2J{}",
+                pager.screen(title, cols).join("\n")
+            )?;
+            stdout.flush()?;
+
+            match pager.handle_key(read_key(&mut tty)?) {
+                Action::Quit => return Ok(()),
+                Action::Search => {
+                    if let Some(pattern) = read_pattern(&mut tty, rows, cols)? {
+                        pager.search(pattern);
+                    }
+                }
+                Action::Continue => {}
+            }
+        }
+    })();
+
+    write!(stdout, "\x1b[?1049l")?;
+    stdout.flush()?;
+    result
+}
+
+/// Reads a search pattern on the last line of the screen.
+/// Returns None if the user pressed Escape.
+fn read_pattern(tty: &mut File, rows: usize, cols: usize) -> Result<Option<String>, ShellError> {
+    let mut stdout = io::stdout();
+    let mut pattern = String::new();
+    loop {
+        let prompt: String = format!("/{}", pattern).chars().take(cols).collect();
+        write!(stdout, "\x1b[{};1H\x1b[2K{}", rows, prompt)?;
+        stdout.flush()?;
+
+        match read_key(tty)? {
+            Key::Enter => return Ok(Some(pattern)),
+            Key::Escape => return Ok(None),
+            Key::Backspace => {
+                pattern.pop();
+            }
+            Key::Char(c) => pattern.push(c),
+            _ => {}
+        }
+    }
+}
+
+/// Blocks until a key is pressed and decodes it.
+pub(crate) fn read_key(tty: &mut File) -> Result<Key, ShellError> {
+    let byte = loop {
+        if let Some(byte) = read_byte(tty)? {
+            break byte;
+        }
+    };
+
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        0x7f | 0x08 => Key::Backspace,
+        byte @ 0x01..=0x1a if byte != b'\t' => Key::Ctrl((b'a' + byte - 1) as char),
+        // Escape sequences for arrows and paging keys, e.g. "\x1b[A".
+        0x1b => match (read_byte(tty)?, read_byte(tty)?) {
+            (Some(b'['), Some(b'A')) => Key::Up,
+            (Some(b'['), Some(b'B')) => Key::Down,
+            (Some(b'['), Some(b'C')) => Key::Right,
+            (Some(b'['), Some(b'D')) => Key::Left,
+            (Some(b'['), Some(b'H')) => Key::Home,
+            (Some(b'['), Some(b'F')) => Key::End,
+            (Some(b'['), Some(code @ b'5')) | (Some(b'['), Some(code @ b'6')) => {
+                // Swallow the trailing '~'.
+                read_byte(tty)?;
+                if code == b'5' {
+                    Key::PageUp
+                } else {
+                    Key::PageDown
+                }
+            }
+            _ => Key::Escape,
+        },
+        byte => {
+            // Decode multi byte UTF-8 characters.
+            let len = match byte {
+                0xf0..=0xff => 4,
+                0xe0..=0xef => 3,
+                0xc0..=0xdf => 2,
+                _ => 1,
+            };
+            let mut bytes = vec![byte];
+            for _ in 1..len {
+                bytes.extend(read_byte(tty)?);
+            }
+            let s = String::from_utf8_lossy(&bytes);
+            Key::Char(s.chars().next().unwrap_or('\u{fffd}'))
+        }
+    };
+    Ok(key)
+}
+
+/// Reads a single byte, None if nothing was typed within a tenth of a
+/// second (see `RawMode`).
+fn read_byte(tty: &mut File) -> Result<Option<u8>, ShellError> {
+    let mut byte = [0];
+    match tty.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+/// Number of (rows, columns) of the terminal, 24x80 if unknown.
+pub(crate) fn terminal_size() -> (usize, usize) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    if ok && size.ws_row > 1 && size.ws_col > 0 {
+        (size.ws_row as usize, size.ws_col as usize)
+    } else {
+        (24, 80)
+    }
+}
+
+/// Puts the terminal in raw mode: keys are delivered as soon as they are
+/// pressed, without echo, and reads time out after a tenth of a second.
+/// The previous settings are restored on drop.
+pub(crate) struct RawMode {
+    fd: i32,
+    original: libc::termios,
+}
+
+impl RawMode {
+    pub(crate) fn enable(tty: &File) -> io::Result<RawMode> {
+        RawMode::with_signals(tty, true)
+    }
+
+    /// Like `enable`, but Ctrl-C and Ctrl-Z are read as keys instead of
+    /// sending signals when `signals` is false.
+    pub(crate) fn with_signals(tty: &File, signals: bool) -> io::Result<RawMode> {
+        let fd = tty.as_raw_fd();
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+        if !signals {
+            raw.c_lflag &= !libc::ISIG;
+        }
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = 1;
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(RawMode { fd, original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(self.fd, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+#[cfg(test)]
+fn numbered_lines(n: usize) -> Vec<String> {
+    (1..=n).map(|i| format!("line {}", i)).collect()
+}
+
+/// Scrolling never goes past the last screen or before the first.
+#[test]
+fn pager_scroll_test() {
+    let mut pager = Pager::new(numbered_lines(25), 10);
+    pager.handle_key(Key::Char(' '));
+    assert_eq!(pager.top, 10);
+    pager.handle_key(Key::Char(' '));
+    assert_eq!(pager.top, 15);
+    pager.handle_key(Key::Down);
+    assert_eq!(pager.top, 15);
+    pager.handle_key(Key::Char('g'));
+    pager.handle_key(Key::Up);
+    assert_eq!(pager.top, 0);
+    assert_eq!(pager.handle_key(Key::Char('q')), Action::Quit);
+}
+
+/// `n` and `N` move between lines matching the search.
+#[test]
+fn pager_search_test() {
+    let mut pager = Pager::new(numbered_lines(30), 5);
+    pager.search("2".to_string());
+    assert_eq!(pager.top, 1);
+    pager.handle_key(Key::Char('n'));
+    assert_eq!(pager.top, 11);
+    pager.handle_key(Key::Char('n'));
+    assert_eq!(pager.top, 19);
+    pager.handle_key(Key::Char('N'));
+    assert_eq!(pager.top, 11);
+
+    pager.search("nope".to_string());
+    assert_eq!(pager.top, 11);
+    assert!(pager.screen("test", 80)[5].contains("Pattern not found: nope"));
+}
@@ -0,0 +1,652 @@
+//! Recursive-descent parser turning a command line, or a whole script,
+//! into a `ShellCommand` tree: lists of statements separated by `;` or
+//! newlines, pipelines, `&`, `>`, `if`, `for`, `while`, groups in `( )` or
+//! `{ }`, functions and variables set with `name=value`.
+//!
+//! Commands are parsed by the registry right away when their text is known.
+//! Commands using `$` or backticks, and function calls, are kept as text and
+//! parsed each time they run, after expansion. So are unknown commands in
+//! the body of a function, which may call functions defined after it.
+
+use crate::command::Registry;
+use crate::error::{ShellError, Span};
+use crate::shell_command::{split_quoted, ShellCommand};
+
+/// Words only meaningful at the start of a statement, closing or
+/// continuing a compound statement.
+const KEYWORDS: &[&str] = &["then", "elif", "else", "fi", "do", "done", "}", ")"];
+
+pub struct Parser<'a> {
+    source: &'a str,
+    /// Byte offset of the next character to parse.
+    pos: usize,
+    registry: &'a Registry,
+    /// Functions of the session and the ones defined so far in the source.
+    /// Their calls are looked up when they run.
+    functions: Vec<String>,
+    /// Number of function bodies being parsed.
+    function_depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(source: &'a str, registry: &'a Registry, functions: Vec<String>) -> Self {
+        Parser {
+            source,
+            pos: 0,
+            registry,
+            functions,
+            function_depth: 0,
+        }
+    }
+
+    /// Parses the whole source. A single statement is returned as it is,
+    /// several as a `Sequence`.
+    pub fn parse(mut self) -> Result<ShellCommand, ShellError> {
+        self.list(&[])
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+
+    fn next_char(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    /// Skips spaces and tabs.
+    fn skip_blanks(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t']).len();
+    }
+
+    /// Skips blanks, statement separators and comments.
+    fn skip_separators(&mut self) {
+        loop {
+            self.skip_blanks();
+            match self.next_char() {
+                Some(';' | '\n') => self.pos += 1,
+                Some('#') => self.pos += self.rest().find('\n').unwrap_or(self.rest().len()),
+                _ => return,
+            }
+        }
+    }
+
+    /// The word starting at the next non blank character, empty at an
+    /// operator or a comment and None at the end. Parentheses are words of
+    /// their own.
+    fn peek_word(&self) -> Option<&'a str> {
+        let rest = self.rest().trim_start_matches([' ', '\t']);
+        match next_token(rest)? {
+            (TokenKind::Word | TokenKind::Paren, len) => Some(&rest[..len]),
+            _ => Some(""),
+        }
+    }
+
+    /// Name of the function defined by the next statement, if it is
+    /// `name() ...`.
+    fn function_name(&self) -> Option<&'a str> {
+        let word = self.peek_word()?;
+        let rest = self.rest().trim_start_matches([' ', '\t']);
+        rest[word.len()..].starts_with("()").then_some(word)
+    }
+
+    /// Consumes the next word, which has to be `keyword`.
+    fn keyword(&mut self, keyword: &str) -> Result<(), ShellError> {
+        self.skip_blanks();
+        match self.peek_word() {
+            Some(word) if word == keyword => {
+                self.pos += word.len();
+                Ok(())
+            }
+            Some(word) => Err(self.unexpected(word)),
+            None => Err(self.unexpected_end(keyword)),
+        }
+    }
+
+    /// Byte offset of the next non blank character.
+    fn word_start(&self) -> usize {
+        self.source.len() - self.rest().trim_start_matches([' ', '\t']).len()
+    }
+
+    /// Error for the `word` found where it does not belong, pointing at it.
+    fn unexpected(&self, word: &str) -> ShellError {
+        let start = self.word_start();
+        let (token, len) = match (word, self.source[start..].chars().next()) {
+            ("", None | Some('\n')) => ("newline".to_string(), 0),
+            ("", Some(c)) => (c.to_string(), c.len_utf8()),
+            (word, _) => (word.to_string(), word.len()),
+        };
+        ShellError::UnexpectedToken(token, Some(Span::new(start, start + len)))
+    }
+
+    /// Error for the source ending before `expected`, pointing at its end.
+    fn unexpected_end(&self, expected: &str) -> ShellError {
+        let end = self.source.len();
+        ShellError::UnexpectedEnd(expected.to_string(), Some(Span::new(end, end)))
+    }
+
+    /// Checks the `$(...)`, `<(...)` and backticks of `text`, a part of the
+    /// source, are closed. Unclosed ones run to the end of the source, they
+    /// are reported from where they start.
+    fn substitutions(&self, text: &str) -> Result<(), ShellError> {
+        match unclosed_substitution(text) {
+            Some(offset) => {
+                let start = self.pos - text.len() + offset;
+                let span = Span::new(start, self.source.len());
+                Err(ShellError::UnclosedSubstitution(
+                    text.to_string(),
+                    Some(span),
+                ))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Parses statements until the end of the source or one of the
+    /// `terminators`, which is left for the caller.
+    fn list(&mut self, terminators: &[&str]) -> Result<ShellCommand, ShellError> {
+        let mut commands = vec![];
+        loop {
+            self.skip_separators();
+            match self.peek_word() {
+                None => break,
+                Some(word) if terminators.contains(&word) => break,
+                Some(word) if KEYWORDS.contains(&word) => return Err(self.unexpected(word)),
+                Some(_) => commands.push(self.statement(terminators)?),
+            }
+        }
+        if commands.len() == 1 {
+            return Ok(commands.remove(0));
+        }
+        Ok(ShellCommand::Sequence(commands))
+    }
+
+    /// Like `list`, for the parts of compound statements which cannot be
+    /// empty.
+    fn body(&mut self, terminators: &[&str]) -> Result<ShellCommand, ShellError> {
+        let start = self.pos;
+        let body = self.list(terminators)?;
+        if matches!(&body, ShellCommand::Sequence(commands) if commands.is_empty()) {
+            self.pos = start;
+            self.skip_separators();
+            return match self.peek_word() {
+                Some(word) => Err(self.unexpected(word)),
+                None => Err(self.unexpected_end(terminators[0])),
+            };
+        }
+        Ok(body)
+    }
+
+    /// Parses one statement, then checks it is followed by a separator or
+    /// one of the `terminators` of the enclosing list.
+    fn statement(&mut self, terminators: &[&str]) -> Result<ShellCommand, ShellError> {
+        let statement = match self.function_name() {
+            Some(name) => self.function(name)?,
+            None => self.pipeline()?,
+        };
+        self.skip_blanks();
+        match self.peek_word() {
+            _ if matches!(self.next_char(), None | Some(';' | '\n' | '#')) => Ok(statement),
+            Some(word) if terminators.contains(&word) => Ok(statement),
+            word => Err(self.unexpected(word.unwrap_or_default())),
+        }
+    }
+
+    /// `if list; then list; [elif list; then list;]... [else list;] fi`
+    fn if_statement(&mut self) -> Result<ShellCommand, ShellError> {
+        self.keyword("if")?;
+        let mut branches = vec![];
+        loop {
+            let condition = self.body(&["then"])?;
+            self.keyword("then")?;
+            let body = self.body(&["elif", "else", "fi"])?;
+            branches.push((condition, body));
+            match self.peek_word() {
+                Some("elif") => self.keyword("elif")?,
+                Some("else") => {
+                    self.keyword("else")?;
+                    let otherwise = self.body(&["fi"])?;
+                    self.keyword("fi")?;
+                    return Ok(ShellCommand::If(branches, Some(Box::new(otherwise))));
+                }
+                _ => {
+                    self.keyword("fi")?;
+                    return Ok(ShellCommand::If(branches, None));
+                }
+            }
+        }
+    }
+
+    /// `for name [in words...]; do list; done`, over the arguments of the
+    /// function running it without `in`.
+    fn for_statement(&mut self) -> Result<ShellCommand, ShellError> {
+        self.keyword("for")?;
+        self.skip_blanks();
+        let name = match self.peek_word() {
+            Some(name) if is_name(name) => name,
+            Some(word) => return Err(self.unexpected(word)),
+            None => return Err(self.unexpected_end("name")),
+        };
+        self.pos += name.len();
+
+        self.skip_blanks();
+        let mut words = None;
+        if self.peek_word() == Some("in") {
+            self.keyword("in")?;
+            let start = self.pos;
+            loop {
+                self.skip_blanks();
+                match self.peek_word() {
+                    _ if matches!(self.next_char(), None | Some(';' | '\n' | '#')) => break,
+                    Some("") => return Err(self.unexpected("")),
+                    Some(word) => self.pos += word.len(),
+                    None => break,
+                }
+            }
+            let text = self.source[start..self.pos].trim();
+            self.substitutions(text)?;
+            words = Some(text.to_string());
+        }
+
+        self.skip_separators();
+        self.keyword("do")?;
+        let body = self.body(&["done"])?;
+        self.keyword("done")?;
+        Ok(ShellCommand::For(name.to_string(), words, Box::new(body)))
+    }
+
+    /// `while list; do list; done`
+    fn while_statement(&mut self) -> Result<ShellCommand, ShellError> {
+        self.keyword("while")?;
+        let condition = self.body(&["do"])?;
+        self.keyword("do")?;
+        let body = self.body(&["done"])?;
+        self.keyword("done")?;
+        Ok(ShellCommand::While(Box::new(condition), Box::new(body)))
+    }
+
+    /// `name() { list; }`
+    fn function(&mut self, name: &str) -> Result<ShellCommand, ShellError> {
+        if !is_name(name) {
+            return Err(self.unexpected(name));
+        }
+        self.skip_blanks();
+        self.pos += name.len() + "()".len();
+        self.skip_separators();
+        // Known before the body so the function can call itself.
+        self.functions.push(name.to_string());
+        self.function_depth += 1;
+        let body = self.group();
+        self.function_depth -= 1;
+        Ok(ShellCommand::Function(name.to_string(), Box::new(body?)))
+    }
+
+    /// `{ list; }`
+    fn group(&mut self) -> Result<ShellCommand, ShellError> {
+        self.keyword("{")?;
+        let body = self.body(&["}"])?;
+        self.keyword("}")?;
+        Ok(body)
+    }
+
+    /// `( list )`
+    fn subshell(&mut self) -> Result<ShellCommand, ShellError> {
+        self.keyword("(")?;
+        let body = self.body(&[")"])?;
+        self.keyword(")")?;
+        Ok(body)
+    }
+
+    /// Commands separated by `|`, run in the background if followed by `&`.
+    fn pipeline(&mut self) -> Result<ShellCommand, ShellError> {
+        let start = self.pos;
+        let mut stages = vec![];
+        loop {
+            stages.push(self.stage()?);
+            self.skip_blanks();
+            if self.next_char() != Some('|') {
+                break;
+            }
+            self.pos += 1;
+        }
+
+        let end = self.pos;
+        let mut stages = stages.into_iter().rev();
+        let last = stages.next().expect("A pipeline has a stage");
+        let pipeline = stages.fold(last, |pipe, stage| {
+            ShellCommand::Pipe(Box::new(stage), Box::new(pipe))
+        });
+
+        match self.background(start, end)? {
+            Some(line) => Ok(ShellCommand::Background(Box::new(pipeline), line)),
+            None => Ok(pipeline),
+        }
+    }
+
+    /// Consumes the `&` following the statement from `start` to `end`, if
+    /// any. Returns the text of the statement to run in the background.
+    fn background(&mut self, start: usize, end: usize) -> Result<Option<String>, ShellError> {
+        if self.next_char() != Some('&') {
+            return Ok(None);
+        }
+        if self.rest().starts_with("&&") {
+            return Err(self.unexpected("&&"));
+        }
+        self.pos += 1;
+        Ok(Some(self.source[start..end].trim().to_string()))
+    }
+
+    /// One command of a pipeline with its redirections: a compound
+    /// statement, a group or a simple command.
+    fn stage(&mut self) -> Result<ShellCommand, ShellError> {
+        self.skip_blanks();
+        let mut command = match self.peek_word() {
+            Some("if") => self.if_statement()?,
+            Some("for") => self.for_statement()?,
+            Some("while") => self.while_statement()?,
+            Some("{") => ShellCommand::Group(Box::new(self.group()?)),
+            Some("(") => ShellCommand::Subshell(Box::new(self.subshell()?)),
+            _ => self.simple_command()?,
+        };
+        loop {
+            self.skip_blanks();
+            if self.next_char() != Some('>') {
+                return Ok(command);
+            }
+            self.pos += 1;
+            let append = self.next_char() == Some('>');
+            if append {
+                self.pos += 1;
+            }
+            self.skip_blanks();
+            let path = match self.peek_word() {
+                Some(word) if !word.is_empty() && word != "(" && word != ")" => word,
+                word => return Err(self.unexpected(word.unwrap_or_default())),
+            };
+            self.pos += path.len();
+            command = ShellCommand::Redirect(Box::new(command), path.to_string(), append);
+        }
+    }
+
+    /// A command without pipes, parsed now if its text is known.
+    fn simple_command(&mut self) -> Result<ShellCommand, ShellError> {
+        let start = self.pos;
+        while let Some(word) = self.peek_word() {
+            if word.is_empty() || word == "(" || word == ")" {
+                break;
+            }
+            self.skip_blanks();
+            self.pos += word.len();
+        }
+        let text = self.source[start..self.pos].trim();
+        if text.is_empty() {
+            let span = Span::new(self.word_start(), self.word_start());
+            return Err(ShellError::EmptyCommandInPipe(text.to_string(), Some(span)));
+        }
+        self.substitutions(text)?;
+        if let Some((name, value)) = text.split_once('=') {
+            if is_name(name) && word_len(text) == text.len() {
+                if split_quoted(value).is_none() {
+                    return Err(self.unexpected_end("quote"));
+                }
+                return Ok(ShellCommand::Assign(name.to_string(), value.to_string()));
+            }
+        }
+        let name = text
+            .split(' ')
+            .find(|w| *w != "--lossy")
+            .unwrap_or_default();
+        let function = self.functions.iter().any(|f| f == name);
+        let found = self.registry.find(name).is_some();
+        let dynamic = |s: &str| s.contains(['$', '`']) || s.contains("<(");
+        // Commands with variables are parsed once they are known, but
+        // their name already has to be a command.
+        let result = match (function, found) {
+            (false, false) if self.function_depth == 0 && !dynamic(name) => {
+                Err(self.registry.unknown(name))
+            }
+            (false, true) if !dynamic(text) => {
+                ShellCommand::parse_single_command(text, self.registry)
+            }
+            _ => return Ok(ShellCommand::Deferred(text.to_string())),
+        };
+        result.map_err(|e| {
+            // Unknown commands point at their name, wrong arguments at the
+            // whole command.
+            let start = start + self.source[start..].find(text).unwrap_or_default();
+            let span = match &e {
+                ShellError::UnknownCommand(name, ..) => {
+                    let name_start = start + text.find(name.as_str()).unwrap_or_default();
+                    Span::new(name_start, name_start + name.len())
+                }
+                _ => Span::new(start, start + text.len()),
+            };
+            e.with_span(span)
+        })
+    }
+}
+
+/// Whether `word` can name a variable or a function.
+pub fn is_name(word: &str) -> bool {
+    let mut chars = word.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// What a token of a command line is, see `tokens`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    /// A command name, an argument or a keyword, with its quotes.
+    Word,
+    /// `;`, `&`, `&&` or a newline.
+    Separator,
+    Pipe,
+    /// `>` or `>>`.
+    Redirect,
+    Paren,
+    /// From `#` to the end of the line.
+    Comment,
+}
+
+/// Splits `source` into tokens the way the parser reads it, e.g. to
+/// highlight a command line as it is typed. Blanks are left out, unclosed
+/// quotes run to the end.
+pub fn tokens(source: &str) -> Vec<(TokenKind, Span)> {
+    let mut tokens = vec![];
+    let mut start = 0;
+    loop {
+        let rest = &source[start..];
+        start += rest.len() - rest.trim_start_matches([' ', '\t']).len();
+        match next_token(&source[start..]) {
+            Some((kind, len)) => {
+                tokens.push((kind, Span::new(start, start + len)));
+                start += len;
+            }
+            None => return tokens,
+        }
+    }
+}
+
+/// Kind and length of the token `s` starts with, None if `s` is empty.
+fn next_token(s: &str) -> Option<(TokenKind, usize)> {
+    let token = match s.chars().next()? {
+        ';' | '\n' => (TokenKind::Separator, 1),
+        '&' if s.starts_with("&&") => (TokenKind::Separator, 2),
+        '&' => (TokenKind::Separator, 1),
+        '|' => (TokenKind::Pipe, 1),
+        '>' if s.starts_with(">>") => (TokenKind::Redirect, 2),
+        '>' => (TokenKind::Redirect, 1),
+        '(' | ')' => (TokenKind::Paren, 1),
+        '#' => (TokenKind::Comment, s.find('\n').unwrap_or(s.len())),
+        _ => (TokenKind::Word, word_len(s)),
+    };
+    Some(token)
+}
+
+/// Length of the word `s` starts with: up to an unquoted blank, `;`,
+/// newline, `|`, `&`, `>` or parenthesis. Quotes, `$(...)` and backticks
+/// are part of the word, unclosed ones run to the end of `s`.
+fn word_len(s: &str) -> usize {
+    let mut i = 0;
+    while let Some(c) = s[i..].chars().next() {
+        let rest = &s[i + c.len_utf8()..];
+        let skip = match c {
+            ' ' | '\t' | ';' | '\n' | '|' | '&' | '>' | '(' | ')' => return i,
+            '\'' => rest.find('\'').map(|end| end + 1),
+            '"' => closing_quote(rest).map(|end| end + 1),
+            '`' => rest.find('`').map(|end| end + 1),
+            '$' | '<' if rest.starts_with('(') => closing_paren(&rest[1..]).map(|end| end + 2),
+            _ => Some(0),
+        };
+        match skip {
+            Some(skip) => i += c.len_utf8() + skip,
+            None => return s.len(),
+        }
+    }
+    i
+}
+
+/// Index in `s` of the `"` closing a `"` just before `s`, skipping `\"`.
+fn closing_quote(s: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' if !escaped => return Some(i),
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+/// Index in `s` of the `)` closing a `(` just before `s`, skipping nested
+/// parentheses and quotes.
+pub fn closing_paren(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '(') => depth += 1,
+            (None, ')') if depth == 0 => return Some(i),
+            (None, ')') => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Byte offset in `text` of the first `$(`, `<(` or backtick never closed,
+/// if any. As in `Session::expand`, there is none in single quotes.
+fn unclosed_substitution(text: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        let rest = &text[i + c.len_utf8()..];
+        let skip = match (quote, c) {
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                0
+            }
+            (Some(q), c) if c == q => {
+                quote = None;
+                0
+            }
+            (Some('\''), _) => 0,
+            (_, '$') | (None, '<') if rest.starts_with('(') => match closing_paren(&rest[1..]) {
+                Some(end) => end + 2,
+                None => return Some(i),
+            },
+            (_, '`') => match rest.find('`') {
+                Some(end) => end + 1,
+                None => return Some(i),
+            },
+            _ => 0,
+        };
+        i += c.len_utf8() + skip;
+    }
+    None
+}
+
+/// Statements nest into a tree, commands with variables or calling
+/// functions are kept as text.
+#[test]
+fn parse_tree_test() {
+    let registry = Registry::with_builtins();
+    let functions = vec!["f".to_string()];
+    let parse = |s| {
+        let parser = Parser::new(s, &registry, functions.clone());
+        parser.parse().map(|c| format!("{:?}", c))
+    };
+    assert_eq!(
+        parse("if f; then jobs; fi").unwrap(),
+        r#"If([(Deferred("f"), Jobs)], None)"#
+    );
+    assert_eq!(
+        parse("for x in a $b; do echo $x | wc -l; done &").unwrap(),
+        concat!(
+            r#"Background(For("x", Some("a $b"), Pipe(Deferred("echo $x"), "#,
+            r#"Wc([], WcOptions { lines: true, words: false, bytes: false, chars: false }))))"#
+        )
+    );
+    assert_eq!(
+        parse("g() {\n  g; while jobs; do echo; done\n}").unwrap(),
+        r#"Function("g", Sequence([Deferred("g"), While(Jobs, Echo("\n"))]))"#
+    );
+    assert_eq!(
+        parse("{ jobs; } >> out; (jobs)").unwrap(),
+        r#"Sequence([Redirect(Group(Jobs), "out", true), Subshell(Jobs)])"#
+    );
+    assert_eq!(
+        parse("diff <(ls a) <(ls b) > out").unwrap(),
+        r#"Redirect(Deferred("diff <(ls a) <(ls b)"), "out", false)"#
+    );
+    assert!(matches!(
+        parse("echo a |"),
+        Err(ShellError::EmptyCommandInPipe(..))
+    ));
+    assert!(matches!(parse("fi"), Err(ShellError::UnexpectedToken(..))));
+    assert!(matches!(
+        parse("if jobs; then"),
+        Err(ShellError::UnexpectedEnd(..))
+    ));
+    assert!(matches!(parse("nope"), Err(ShellError::UnknownCommand(..))));
+}
+
+/// Parse errors point at the word at fault, underlined on its own line.
+#[test]
+fn error_span_test() {
+    let registry = Registry::with_builtins();
+    let underline = |s| {
+        let e = Parser::new(s, &registry, vec![]).parse().unwrap_err();
+        e.underline(s).unwrap()
+    };
+    assert_eq!(
+        underline("cat a | fnd-string x"),
+        "cat a | fnd-string x\n        ^^^^^^^^^^"
+    );
+    assert_eq!(
+        underline("echo a\n\tfor x in a; do head -n x; done"),
+        "\tfor x in a; do head -n x; done\n\t               ^^^^^^^^^"
+    );
+    assert_eq!(underline("echo a | | wc"), "echo a | | wc\n         ^");
+    assert_eq!(underline("if ls; then"), "if ls; then\n           ^");
+    assert_eq!(underline("echo é && ls"), "echo é && ls\n       ^^");
+    assert_eq!(
+        underline("echo a; echo $("),
+        "echo a; echo $(\n             ^^"
+    );
+    assert_eq!(
+        underline("for x in `ls; do echo; done"),
+        "for x in `ls; do echo; done\n         ^^^^^^^^^^^^^^^^^^"
+    );
+    assert_eq!(
+        underline("echo '$(' \"$(echo ')')\" `date"),
+        "echo '$(' \"$(echo ')')\" `date\n                        ^^^^^"
+    );
+    assert_eq!(
+        underline("diff a <(head a"),
+        "diff a <(head a\n       ^^^^^^^^"
+    );
+}
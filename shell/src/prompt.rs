@@ -0,0 +1,116 @@
+//! The prompt shown before each command line, set by the `PROMPT`
+//! variable of the session.
+
+use std::path::{Path, PathBuf};
+
+use crate::session::Session;
+
+/// Prompt used when `PROMPT` is not set.
+pub const DEFAULT_PROMPT: &str = "> ";
+
+/// The prompt of `session`, its `PROMPT` variable with escapes replaced:
+///
+/// - `\w` the current directory, with the home directory shown as `~`
+/// - `\W` the last component of the current directory
+/// - `\u` the user name
+/// - `\h` the host name
+/// - `\?` the status of the last command
+/// - `\t` the time, as HH:MM:SS
+/// - `\n` a newline and `\\` a backslash
+///
+/// Other backslashes are kept as they are.
+pub fn render(session: &Session) -> String {
+    let template = session.var("PROMPT").unwrap_or(DEFAULT_PROMPT);
+    let mut prompt = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            prompt.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('w') => prompt.push_str(&tilde(&current_dir(session))),
+            Some('W') => {
+                let dir = current_dir(session);
+                match dir.file_name() {
+                    Some(name) => prompt.push_str(&name.to_string_lossy()),
+                    None => prompt.push_str(&dir.to_string_lossy()),
+                }
+            }
+            Some('u') => prompt.push_str(&user()),
+            Some('h') => prompt.push_str(&host()),
+            Some('?') => prompt.push_str(&session.status().to_string()),
+            Some('t') => prompt.push_str(&time()),
+            Some('n') => prompt.push('\n'),
+            Some('\\') => prompt.push('\\'),
+            Some(c) => {
+                prompt.push('\\');
+                prompt.push(c);
+            }
+            None => prompt.push('\\'),
+        }
+    }
+    prompt
+}
+
+/// The current directory of `session` as an absolute path.
+fn current_dir(session: &Session) -> PathBuf {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    cwd.join(session.cwd()).components().collect()
+}
+
+/// `dir` with the home directory replaced by `~`.
+fn tilde(dir: &Path) -> String {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    match home.as_deref().and_then(|home| dir.strip_prefix(home).ok()) {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => dir.display().to_string(),
+    }
+}
+
+fn user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("LOGNAME"))
+        .unwrap_or_default()
+}
+
+fn host() -> String {
+    let mut name = [0u8; 256];
+    let ok = unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } == 0;
+    if !ok {
+        return String::new();
+    }
+    let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    String::from_utf8_lossy(&name[..len]).into_owned()
+}
+
+/// The local time, as HH:MM:SS.
+fn time() -> String {
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        libc::localtime_r(&now, &mut tm);
+    }
+    format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec)
+}
+
+/// Escapes are replaced by their value, other text is kept.
+#[test]
+fn render_test() {
+    let mut session = Session::new();
+    assert_eq!(render(&session), DEFAULT_PROMPT);
+
+    session.set_cwd("/tmp/prompt-test");
+    session.set_status(2);
+    session.set_var("PROMPT", r"\W [\?] \\ \x$ ");
+    assert_eq!(render(&session), r"prompt-test [2] \ \x$ ");
+
+    session.set_var("PROMPT", r"\w\n");
+    assert_eq!(render(&session), "/tmp/prompt-test\n");
+
+    session.set_var("PROMPT", r"\t");
+    let time = render(&session);
+    assert_eq!(time.len(), 8);
+    assert_eq!(time.matches(':').count(), 2);
+}
@@ -0,0 +1,453 @@
+//! Entry point for running command lines, in the binary's loop or in
+//! programs embedding the shell. A session holds everything commands
+//! change for the commands after them, so sessions in the same process
+//! do not affect each other.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use crate::command::Registry;
+use crate::commands::{file_contents, ChangeMode};
+use crate::error::ShellError;
+use crate::jobs::{CancellationToken, Jobs};
+use crate::parser::{closing_paren, is_name, Parser};
+use crate::shell_command::ShellCommand;
+use crate::{CommandOutput, OutputFormat};
+
+/// Settings changed by commands such as `output-format` or `dry-run`.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// How output is printed when a command does not ask for a format.
+    pub output_format: OutputFormat,
+    /// How commands changing files go about it.
+    pub change_mode: ChangeMode,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            output_format: OutputFormat::Text,
+            change_mode: ChangeMode::from_env(),
+        }
+    }
+}
+
+/// Where the statements of a list, e.g. a script or the body of a loop,
+/// hand their output and errors as each one ends, with the output format
+/// of the session. See `Session::set_output`.
+pub type OutputSink = Arc<dyn Fn(Result<CommandOutput, ShellError>, OutputFormat) + Send + Sync>;
+
+/// Runs command lines with the commands of its registry.
+pub struct Session {
+    registry: Registry,
+    /// Directory relative paths are resolved against. Empty for the
+    /// directory the process runs in, the process' own current directory
+    /// is never changed.
+    cwd: PathBuf,
+    variables: HashMap<String, String>,
+    /// Functions defined by the command lines run so far, by name.
+    functions: HashMap<String, ShellCommand>,
+    /// Arguments of the function running, `$1`, `$2`...
+    args: Vec<String>,
+    /// Status of the last command, `$?`.
+    status: i32,
+    /// Status given to `exit`, once it ran.
+    exit_status: Option<i32>,
+    /// Stops or pauses the command line running, see `CancellationToken`.
+    token: CancellationToken,
+    jobs: Jobs,
+    /// Set when statements print their output as they end, see `set_output`.
+    output: Option<OutputSink>,
+    /// Whether output goes into a pipe, a file or a substitution, see
+    /// `captured`.
+    capturing: bool,
+    /// Read ends of the pipes `<(...)` were replaced by, open until the
+    /// command line reading them is done. See `with_substitutions`.
+    substitutions: Vec<File>,
+    pub settings: Settings,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session::with_registry(Registry::with_builtins())
+    }
+}
+
+impl Session {
+    /// A session with the built-in commands.
+    pub fn new() -> Self {
+        Session::default()
+    }
+
+    /// A session with the commands of `registry` instead of the built-ins.
+    pub fn with_registry(registry: Registry) -> Self {
+        Session {
+            registry,
+            cwd: PathBuf::new(),
+            variables: HashMap::new(),
+            functions: HashMap::new(),
+            args: vec![],
+            status: 0,
+            exit_status: None,
+            token: CancellationToken::foreground(),
+            jobs: Jobs::default(),
+            output: None,
+            capturing: false,
+            substitutions: vec![],
+            settings: Settings::default(),
+        }
+    }
+
+    /// Copy of this session for a command line run in the background. It
+    /// starts in the same directory with the same settings, but changes to
+    /// them only last for the command line. Its output is returned once it
+    /// is done.
+    pub fn background(&self) -> Self {
+        let mut session = self.subshell();
+        session.token = CancellationToken::background();
+        session.output = None;
+        session.capturing = false;
+        session
+    }
+
+    /// Copy of this session for statements in `( )`, like `background` but
+    /// stopped along with this session.
+    pub fn subshell(&self) -> Self {
+        Session {
+            registry: self.registry.clone(),
+            cwd: self.cwd.clone(),
+            variables: self.variables.clone(),
+            functions: self.functions.clone(),
+            args: self.args.clone(),
+            status: self.status,
+            exit_status: None,
+            token: self.token.clone(),
+            jobs: Jobs::default(),
+            output: self.output.clone(),
+            capturing: self.capturing,
+            substitutions: vec![],
+            settings: self.settings.clone(),
+        }
+    }
+
+    /// The commands of this session.
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// The commands of this session, to register more.
+    pub fn registry_mut(&mut self) -> &mut Registry {
+        &mut self.registry
+    }
+
+    /// The current directory of this session, relative to the one of the
+    /// process unless it is absolute.
+    pub fn cwd(&self) -> &Path {
+        if self.cwd.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            &self.cwd
+        }
+    }
+
+    /// Changes the current directory of this session, see `cwd`.
+    pub fn set_cwd(&mut self, cwd: impl Into<PathBuf>) {
+        self.cwd = cwd.into();
+    }
+
+    /// `path` relative to the current directory of this session, as seen
+    /// from the process. Absolute paths are left as they are.
+    pub fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
+        if self.cwd.as_os_str().is_empty() {
+            return path.as_ref().to_path_buf();
+        }
+        self.cwd.join(path).components().collect()
+    }
+
+    /// Token long-running commands check to stop when asked to.
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    /// Replaces the token of this session, e.g. for a job to be stopped
+    /// on its own.
+    pub fn set_token(&mut self, token: CancellationToken) {
+        self.token = token;
+    }
+
+    /// The command lines started in the background by this session.
+    pub fn jobs(&self) -> &Jobs {
+        &self.jobs
+    }
+
+    /// The command lines started in the background by this session, to
+    /// start, wait for or remove them.
+    pub fn jobs_mut(&mut self) -> &mut Jobs {
+        &mut self.jobs
+    }
+
+    /// Hands the output and errors of each statement of a list to `sink` as
+    /// soon as it ends, instead of returning them with the rest of the
+    /// list. Output piped, redirected or substituted is still returned to
+    /// where it goes. None, the default, returns everything.
+    pub fn set_output(&mut self, sink: Option<OutputSink>) {
+        self.output = sink;
+    }
+
+    /// Where statements hand their output as they end, if anywhere.
+    pub(crate) fn output(&self) -> Option<&OutputSink> {
+        self.output.as_ref()
+    }
+
+    /// Runs `f` with every output returned instead of handed to the sink,
+    /// for output going into a pipe, a file or a substitution.
+    pub(crate) fn captured<T>(&mut self, f: impl FnOnce(&mut Session) -> T) -> T {
+        let sink = self.output.take();
+        let capturing = std::mem::replace(&mut self.capturing, true);
+        let result = f(self);
+        self.output = sink;
+        self.capturing = capturing;
+        result
+    }
+
+    /// Runs `f`, then closes the pipes of the `<(...)` expanded meanwhile.
+    pub(crate) fn with_substitutions<T>(&mut self, f: impl FnOnce(&mut Session) -> T) -> T {
+        let opened = self.substitutions.len();
+        let result = f(self);
+        self.substitutions.truncate(opened);
+        result
+    }
+
+    /// Whether the command running has its output captured, see `captured`.
+    pub(crate) fn is_captured(&self) -> bool {
+        self.capturing
+    }
+
+    /// Value of the variable `name`, if set.
+    pub fn var(&self, name: &str) -> Option<&str> {
+        self.variables.get(name).map(String::as_str)
+    }
+
+    /// Sets the variable `name` to `value`.
+    pub fn set_var(&mut self, name: &str, value: impl Into<String>) {
+        self.variables.insert(name.to_string(), value.into());
+    }
+
+    /// Defines the function `name`, replacing any function of that name.
+    pub fn define_function(&mut self, name: &str, body: ShellCommand) {
+        self.functions.insert(name.to_string(), body);
+    }
+
+    /// The body of the function `name`, if defined.
+    pub fn function(&self, name: &str) -> Option<&ShellCommand> {
+        self.functions.get(name)
+    }
+
+    /// Arguments of the function running, empty outside functions.
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Runs the function `name` with `args` as its positional arguments.
+    pub fn call(&mut self, name: &str, args: Vec<String>) -> Result<CommandOutput, ShellError> {
+        let body = self
+            .function(name)
+            .cloned()
+            .ok_or_else(|| self.registry.unknown(name))?;
+        let caller_args = std::mem::replace(&mut self.args, args);
+        let result = body.execute_shell_command(self);
+        self.args = caller_args;
+        result
+    }
+
+    /// Exit status of the last command run, 0 if none ran yet.
+    pub fn status(&self) -> i32 {
+        self.status
+    }
+
+    /// Sets the status of the last command, as each command of a line
+    /// finishes or when the line fails to parse, see `ShellError::status`.
+    pub fn set_status(&mut self, status: i32) {
+        self.status = status;
+    }
+
+    /// Ends the session with `status`. Commands already running finish,
+    /// programs running the session stop once `exit_status` is set.
+    pub fn exit(&mut self, status: i32) {
+        self.exit_status = Some(status);
+    }
+
+    /// Status the session was ended with by `exit`, if it was.
+    pub fn exit_status(&self) -> Option<i32> {
+        self.exit_status
+    }
+
+    /// Replaces variables in a command line by their value and runs the
+    /// command lines in `$(...)` or backticks to put their output in their
+    /// place. Nothing is replaced in single quotes.
+    ///
+    /// `<(...)` is replaced by a `/dev/fd` path the output of its command
+    /// line is read from, e.g. to `diff` the output of two commands. The
+    /// path can be read until the command line it is part of is done.
+    ///
+    /// Variables are `$name` or `${name}`, `$?` for the status of the last
+    /// command and `$1` to `$9`, `$#` and `$@` for the arguments of the
+    /// function running. Unset variables are replaced by nothing.
+    ///
+    /// Values and output are split into words separated by single spaces,
+    /// unless in double quotes. Words the parser would read as more than
+    /// text, e.g. `|`, are quoted.
+    pub fn expand(&mut self, line: &str) -> Result<String, ShellError> {
+        let unclosed = || ShellError::UnclosedSubstitution(line.to_string(), None);
+        let mut expanded = String::new();
+        let mut quote = None;
+        let mut rest = line;
+        while let Some(c) = rest.chars().next() {
+            rest = &rest[c.len_utf8()..];
+            let inner = match (quote, c) {
+                (None, '\'' | '"') => {
+                    quote = Some(c);
+                    None
+                }
+                (Some(q), c) if c == q => {
+                    quote = None;
+                    None
+                }
+                (Some('\''), _) => None,
+                (_, '$') if rest.starts_with('(') => {
+                    let end = closing_paren(&rest[1..]).ok_or_else(unclosed)?;
+                    let inner = &rest[1..end + 1];
+                    rest = &rest[end + 2..];
+                    Some(inner)
+                }
+                (None, '<') if rest.starts_with('(') => {
+                    let end = closing_paren(&rest[1..]).ok_or_else(unclosed)?;
+                    let path = self.substitute(&rest[1..end + 1])?;
+                    rest = &rest[end + 2..];
+                    push_value(&mut expanded, &path, false);
+                    continue;
+                }
+                (_, '`') => {
+                    let end = rest.find('`').ok_or_else(unclosed)?;
+                    let inner = &rest[..end];
+                    rest = &rest[end + 1..];
+                    Some(inner)
+                }
+                (_, '$') => match self.variable(rest) {
+                    Some((value, len)) => {
+                        rest = &rest[len..];
+                        push_value(&mut expanded, &value, quote.is_some());
+                        continue;
+                    }
+                    None => None,
+                },
+                _ => None,
+            };
+            match inner {
+                Some(inner) => {
+                    let text = self.captured(|s| s.run(inner))?.lines().join("\n");
+                    push_value(&mut expanded, &text, quote.is_some());
+                }
+                None => expanded.push(c),
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// Runs `command` and returns the path of a pipe its output can be read
+    /// from, as `>` would write it to a file. The pipe is written by a
+    /// thread as the output may not fit in it, the thread stops once the
+    /// read end is closed.
+    fn substitute(&mut self, command: &str) -> Result<String, ShellError> {
+        let bytes = file_contents(&self.captured(|s| s.run(command))?);
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let (reader, mut writer) =
+            unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        thread::spawn(move || writer.write_all(&bytes));
+        let path = format!("/dev/fd/{}", reader.as_raw_fd());
+        self.substitutions.push(reader);
+        Ok(path)
+    }
+
+    /// Value of the variable `rest` starts with, just after a `$`, and the
+    /// length of its name. None if no variable name follows.
+    fn variable(&self, rest: &str) -> Option<(String, usize)> {
+        let (name, len) = match rest.chars().next()? {
+            '{' => {
+                let end = rest.find('}')?;
+                (&rest[1..end], end + 1)
+            }
+            '?' | '#' | '@' | '1'..='9' => (&rest[..1], 1),
+            _ => {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                (&rest[..len], len)
+            }
+        };
+        let value = match name {
+            "?" => self.status.to_string(),
+            "#" => self.args.len().to_string(),
+            "@" => self.args.join(" "),
+            _ => match name.parse::<usize>() {
+                Ok(n) => self
+                    .args
+                    .get(n.wrapping_sub(1))
+                    .cloned()
+                    .unwrap_or_default(),
+                Err(_) if is_name(name) => self.var(name).unwrap_or_default().to_string(),
+                Err(_) => return None,
+            },
+        };
+        Some((value, len))
+    }
+
+    /// Parses a command line, or a script of several lines, without running
+    /// it. Calls to the functions of this session are parsed as such.
+    pub fn parse(&self, line: &str) -> Result<ShellCommand, ShellError> {
+        let functions = self.functions.keys().cloned().collect();
+        Parser::new(line, &self.registry, functions).parse()
+    }
+
+    /// Runs a parsed command. Its status is kept as it runs, see `status`.
+    pub fn execute(&mut self, command: &ShellCommand) -> Result<CommandOutput, ShellError> {
+        command.execute_shell_command(self)
+    }
+
+    /// Parses and runs a command line. Variables and substitutions are
+    /// expanded as each command runs.
+    pub fn run(&mut self, line: &str) -> Result<CommandOutput, ShellError> {
+        let command = self.parse(line).inspect_err(|e| self.status = e.status())?;
+        self.execute(&command)
+    }
+}
+
+/// Appends the `value` of a variable or substitution to `expanded`, split
+/// into words unless `quoted`.
+fn push_value(expanded: &mut String, value: &str, quoted: bool) {
+    if quoted {
+        expanded.push_str(&value.replace('"', "\\\""));
+    } else {
+        let words: Vec<String> = value.split_whitespace().map(quote_word).collect();
+        expanded.push_str(&words.join(" "));
+    }
+}
+
+/// `word` quoted if the parser would not read it as a single word of text.
+fn quote_word(word: &str) -> String {
+    if !word.contains(|c| "|&<'\"".contains(c)) {
+        word.to_string()
+    } else if word.contains('\'') {
+        format!("\"{}\"", word.replace('"', "\\\""))
+    } else {
+        format!("'{}'", word)
+    }
+}
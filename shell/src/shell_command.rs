@@ -1,158 +1,778 @@
-use crate::commands::more_file;
-use crate::error::ShellError;
-use crate::CommandOutput;
+use std::fmt;
 use std::path::PathBuf;
 
-use crate::commands::*;
-
-// Step #12
-// Study the ShellCommand enum.
-// Notice the variants correspond to the command you implemented.
+use crate::builtins::Echo;
+use crate::command::*;
+use crate::commands::tee;
+use crate::error::ShellError;
+use crate::parser::{tokens, Parser, TokenKind};
+use crate::session::Session;
+use crate::CommandOutput;
 
-/// Represents all the possible commands that our shell can execute.
-/// Each one of these variants has a corresponding function in
-/// `commands` which does the actual work!
-#[derive(Debug, Clone)]
+/// A command line parsed into the commands to run. Commands themselves
+/// implement `Command` and are found in a `Registry`, this only glues
+/// them together.
+#[derive(Clone)]
 pub enum ShellCommand {
-    /// Print the contents of file to screen.
-    More(PathBuf),
-    /// Takes 0 or more files and concats their contents.
-    Cat(Vec<PathBuf>),
-    /// Print all entries in a directory.
-    Ls(PathBuf),
-    /// Change current working directory.
-    Cd(PathBuf),
-    /// Look for file name recursively under directory.
-    FindFile(String, PathBuf),
-    /// Returns all lines that match the second string, in the
-    /// first string.
-    /// If Option is None, this command is expecting pipe input.
-    /// Can take input from  pipe.
-    /// FindString(content, matcher)
-    FindString(Option<String>, String),
-    /// Replaces all instances of `from` with `to` in
-    /// contents.
-    /// Can take input from  pipe.
-    /// SubsString(contents, from, to)
-    SubsString(Option<String>, String, String),
+    /// A single command, with its arguments.
+    Builtin(Box<dyn Command>),
+    /// Text command whose pipe input is decoded as lossy UTF-8, replacing
+    /// invalid sequences instead of failing.
+    Lossy(Box<ShellCommand>),
     /// Allows output of first command to be piped into second command.
     Pipe(Box<ShellCommand>, Box<ShellCommand>),
+    /// Command line ending with `&`, run as a job while the shell carries
+    /// on. Background(command, line)
+    Background(Box<ShellCommand>, String),
+    /// A command kept as text because it uses `$` or backticks, or calls a
+    /// function. It is expanded and parsed each time it runs.
+    Deferred(String),
+    /// A call to a function of the session. Call(name, args)
+    Call(String, Vec<String>),
+    /// Statements run one after the other, separated by `;` or newlines.
+    /// A failing statement does not stop the ones after it.
+    Sequence(Vec<ShellCommand>),
+    /// `if`, with the condition and body of the `if` and each `elif`, and
+    /// the body of the `else` if any. If(branches, otherwise)
+    If(Vec<(ShellCommand, ShellCommand)>, Option<Box<ShellCommand>>),
+    /// `for`, with the words to loop over as written, expanded when it
+    /// runs, or None for the arguments of the function. For(name, words, body)
+    For(String, Option<String>, Box<ShellCommand>),
+    /// `while`. While(condition, body)
+    While(Box<ShellCommand>, Box<ShellCommand>),
+    /// Defines a function of the session. Function(name, body)
+    Function(String, Box<ShellCommand>),
+    /// Statements in `{ }`, run in the session like any others, with their
+    /// output combined.
+    Group(Box<ShellCommand>),
+    /// Statements in `( )`, run in a copy of the session so changes such as
+    /// `cd` do not last after them.
+    Subshell(Box<ShellCommand>),
+    /// Writes the output of a command to a file instead, `>` or `>>` to
+    /// append. Redirect(command, file, append)
+    Redirect(Box<ShellCommand>, String, bool),
+    /// Sets a variable of the session, `name=value`. The value is expanded
+    /// when it runs. Assign(name, value)
+    Assign(String, String),
 }
 
-impl ShellCommand {
-    /// Creates the new shell command. Returns ShellError if unable to parse command.
-    /// Possible errors:
-    pub fn create_shell_command(cli_input: &str) -> Result<ShellCommand, ShellError> {
-        // Pipe command. Come back only after you have implemented
-        // the full shell for single commands!!!
-
-        // Split commands by pipes.
-        let commands: Vec<&str> = unimplemented!();
-
-        for c in &commands {
-            if c.is_empty() {
-                return Err(ShellError::EmptyCommandInPipe(c.to_string()));
-            }
-        }
-
-        // Write the recursive helper function that creates a "tree"
-        // of commands using ShellCommand::Pipe.
-        // This is pretty tricky.
-        // Use the function parse_single_command below.
-        // We recommend copying the solution for this code.
-        // Unless you really wanna try it.
-        // Either way, study how the solution works.
-        fn make_pipe(list: &[&str]) -> Result<ShellCommand, ShellError> {
-            match list {
-                // base case: single element left.
-                &[last] => unimplemented!(),
-                list => {
-                    // Indexing guaranteed to work due to pattern matching.
-                    unimplemented!()
-                }
+/// Single commands show as the command itself, e.g. `Head([], 10)`.
+impl fmt::Debug for ShellCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShellCommand::Builtin(c) => c.fmt(f),
+            ShellCommand::Lossy(c) => f.debug_tuple("Lossy").field(c).finish(),
+            ShellCommand::Pipe(c1, c2) => f.debug_tuple("Pipe").field(c1).field(c2).finish(),
+            ShellCommand::Background(c, _) => f.debug_tuple("Background").field(c).finish(),
+            ShellCommand::Deferred(text) => f.debug_tuple("Deferred").field(text).finish(),
+            ShellCommand::Call(name, args) => {
+                f.debug_tuple("Call").field(name).field(args).finish()
+            }
+            ShellCommand::Sequence(commands) => f.debug_tuple("Sequence").field(commands).finish(),
+            ShellCommand::If(branches, otherwise) => f
+                .debug_tuple("If")
+                .field(branches)
+                .field(otherwise)
+                .finish(),
+            ShellCommand::For(name, words, body) => f
+                .debug_tuple("For")
+                .field(name)
+                .field(words)
+                .field(body)
+                .finish(),
+            ShellCommand::While(condition, body) => {
+                f.debug_tuple("While").field(condition).field(body).finish()
+            }
+            ShellCommand::Function(name, body) => {
+                f.debug_tuple("Function").field(name).field(body).finish()
+            }
+            ShellCommand::Group(body) => f.debug_tuple("Group").field(body).finish(),
+            ShellCommand::Subshell(body) => f.debug_tuple("Subshell").field(body).finish(),
+            ShellCommand::Redirect(c, path, append) => f
+                .debug_tuple("Redirect")
+                .field(c)
+                .field(path)
+                .field(append)
+                .finish(),
+            ShellCommand::Assign(name, value) => {
+                f.debug_tuple("Assign").field(name).field(value).finish()
             }
         }
+    }
+}
 
-        make_pipe(&commands)
+impl From<&dyn Command> for ShellCommand {
+    fn from(command: &dyn Command) -> Self {
+        ShellCommand::Builtin(command.clone_box())
+    }
+}
+
+impl ShellCommand {
+    /// Creates the new shell command from the commands in `registry`.
+    /// Returns ShellError if unable to parse command. See `Parser`.
+    pub fn create_shell_command(
+        cli_input: &str,
+        registry: &Registry,
+    ) -> Result<ShellCommand, ShellError> {
+        Parser::new(cli_input, registry, vec![]).parse()
     }
 
     /// Parses a single command that does not contain any pipes.
-    fn parse_single_command(command: &str) -> Result<ShellCommand, ShellError> {
-        // Remove whitespace from command.
+    pub(crate) fn parse_single_command(
+        command: &str,
+        registry: &Registry,
+    ) -> Result<ShellCommand, ShellError> {
         let command = command.trim();
 
-        // #Step 13
-        // Split command by space.
-        let parts: Vec<&str> = unimplemented!();
-
-        // Step #14
-        // Implement the code for most basic commands:
-        // ls, more, cd, subs-string, find-string and cat.
-        match parts.as_slice() {
-            // Handle case for ls with no arguments.
-            ["ls"] => Ok(ShellCommand::Ls(PathBuf::from("."))),
-            ["ls", path] => unimplemented!(),
-            ["more", path] => unimplemented!(),
-            ["cd", path] => unimplemented!(),
-            ["find-file", path, dir] => Ok(ShellCommand::FindFile(
-                path.to_string(),
-                PathBuf::from(dir),
-            )),
-            ["find-string", content, string] => unimplemented!(),
-
-            // Find string with 1 argument, used for a pipe.
-            // Requires pipes! Do later once you have finished shell
-            // with no pipes!
-            ["find-string", string] => unimplemented!(),
-            ["subs-string", content, from, to] => unimplemented!(),
-
-            // Requires pipes! Do later once you have finished shell
-            // with no pipes!
-            ["subs-string", from, to] => unimplemented!(),
-
-            parts => {
-                // Hard cases, Rust's pattern matching is unable to handle their
-                // matching. Specifically `cat` since it takes multiple files.
-                if parts[0] == "cat" {
-                    let args = &parts[1..];
-                    if args.is_empty() {
-                        // Step #15
-                        // return KnownCommandWrongArgs error!
-                    } else {
-                        // Step #16
-                        // Create Cat command!
+        // `command <<< text` feeds text to the command, as if piped from echo.
+        // A quoted `<<<` is part of a word, not the operator.
+        let here_string = tokens(command).into_iter().find(|(kind, span)| {
+            *kind == TokenKind::Word && command[span.start..span.end].starts_with("<<<")
+        });
+        if let Some((_, span)) = here_string {
+            let (command, text) = (&command[..span.start], &command[span.start + 3..]);
+            let parsed = ShellCommand::parse_single_command(command, registry)?;
+            let text = split_quoted(text.trim())
+                .ok_or_else(|| registry.wrong_args(command.trim()))?
+                .join(" ");
+            let echo = ShellCommand::Builtin(Box::new(Echo(text + "\n")));
+            return Ok(ShellCommand::Pipe(Box::new(echo), Box::new(parsed)));
+        }
+
+        // Text commands accept --lossy anywhere in their arguments.
+        if command.split(' ').any(|p| p == "--lossy") {
+            let rest: Vec<&str> = command.split(' ').filter(|p| *p != "--lossy").collect();
+            let parsed = registry.parse(&rest.join(" "))?;
+            if !parsed.accepts_lossy() {
+                return Err(registry.wrong_args(command));
+            }
+            return Ok(ShellCommand::Lossy(Box::new(ShellCommand::Builtin(parsed))));
+        }
+
+        registry.parse(command).map(ShellCommand::Builtin)
+    }
+
+    /// Runs the command in `session`.
+    /// Handles piped commands by piping their input together.
+    pub fn execute_shell_command(
+        &self,
+        session: &mut Session,
+    ) -> Result<CommandOutput, ShellError> {
+        self.execute_with_input(None, false, session)
+    }
+
+    /// Executes this command with `input`, the output of the previous
+    /// command in a pipe if any. Text input is decoded as lossy UTF-8 if
+    /// `lossy`.
+    fn execute_with_input(
+        &self,
+        input: Option<CommandOutput>,
+        lossy: bool,
+        session: &mut Session,
+    ) -> Result<CommandOutput, ShellError> {
+        match self {
+            ShellCommand::Builtin(command) => {
+                let result = match (command.pipe_input(), input.is_some()) {
+                    (PipeInput::Required, false) => {
+                        Err(ShellError::ExpectedPipeInput(self.clone()))
+                    }
+                    (PipeInput::Unexpected, true) => {
+                        Err(ShellError::UnexpectedPipeInput(self.clone()))
+                    }
+                    (PipeInput::NotAccepted, true) => {
+                        Err(ShellError::PipeInputNotAccepted(self.clone()))
+                    }
+                    _ => command.execute(&mut Context {
+                        input,
+                        lossy,
+                        session,
+                    }),
+                };
+                // `exit` sets the status itself, `exit` alone keeps the last one.
+                let status = match session.exit_status() {
+                    Some(status) => status,
+                    None => command.status(&result),
+                };
+                session.set_status(status);
+                result
+            }
+            ShellCommand::Lossy(c) => c.execute_with_input(input, true, session),
+            ShellCommand::Pipe(c1, c2) => {
+                match session.captured(|session| c1.execute_with_input(input, lossy, session)) {
+                    Err(ShellError::PartialFailure(cmd_output, e)) => after_partial_failure(
+                        *e,
+                        c2.execute_with_input(Some(cmd_output), false, session),
+                    ),
+                    cmd_output => c2.execute_with_input(Some(cmd_output?), false, session),
+                }
+            }
+            ShellCommand::Background(c, line) => {
+                let job = session.background();
+                let id = session.jobs_mut().spawn(line, (**c).clone(), job);
+                session.set_status(0);
+                Ok(CommandOutput::Single(format!("[{}]", id)))
+            }
+            ShellCommand::Deferred(text) => session.with_substitutions(|session| {
+                let command = session
+                    .expand(text)
+                    .and_then(|text| ShellCommand::parse_expanded(&text, session))
+                    .inspect_err(|e| session.set_status(e.status()))?;
+                command.execute_with_input(input, lossy, session)
+            }),
+            ShellCommand::Call(name, args) => {
+                if input.is_some() {
+                    return Err(ShellError::PipeInputNotAccepted(self.clone()));
+                }
+                session.call(name, args.clone())
+            }
+            ShellCommand::Sequence(commands) => {
+                let mut list = ListOutput::new();
+                for command in commands {
+                    if !list.add(command.execute_shell_command(session), session) {
+                        break;
                     }
                 }
+                list.finish()
+            }
+            ShellCommand::If(branches, otherwise) => {
+                let mut list = ListOutput::new();
+                for (condition, body) in branches {
+                    if !list.add(condition.execute_shell_command(session), session) {
+                        return list.finish();
+                    }
+                    if session.status() == 0 {
+                        list.add(body.execute_shell_command(session), session);
+                        return list.finish();
+                    }
+                }
+                match otherwise {
+                    Some(body) => {
+                        list.add(body.execute_shell_command(session), session);
+                    }
+                    None => session.set_status(0),
+                }
+                list.finish()
+            }
+            ShellCommand::For(name, words, body) => {
+                let words = match words {
+                    Some(words) => split_quoted(&session.expand(words)?)
+                        .ok_or_else(|| ShellError::UnexpectedEnd("'".to_string(), None))?,
+                    None => session.args().to_vec(),
+                };
+                let mut list = ListOutput::new();
+                session.set_status(0);
+                for word in words {
+                    let checkpoint = session.token().checkpoint();
+                    if !list.add(checkpoint.map(|_| CommandOutput::None), session) {
+                        break;
+                    }
+                    session.set_var(name, word);
+                    if !list.add(body.execute_shell_command(session), session) {
+                        break;
+                    }
+                }
+                list.finish()
+            }
+            ShellCommand::While(condition, body) => {
+                let mut list = ListOutput::new();
+                let mut status = 0;
+                loop {
+                    let checkpoint = session.token().checkpoint();
+                    if !list.add(checkpoint.map(|_| CommandOutput::None), session)
+                        || !list.add(condition.execute_shell_command(session), session)
+                        || session.status() != 0
+                    {
+                        break;
+                    }
+                    let running = list.add(body.execute_shell_command(session), session);
+                    status = session.status();
+                    if !running {
+                        break;
+                    }
+                }
+                // The status of the body, the condition failing is how loops end.
+                session.set_status(status);
+                list.finish()
+            }
+            ShellCommand::Function(name, body) => {
+                session.define_function(name, (**body).clone());
+                session.set_status(0);
+                Ok(CommandOutput::None)
+            }
+            ShellCommand::Group(body) => {
+                if input.is_some() {
+                    return Err(ShellError::PipeInputNotAccepted(self.clone()));
+                }
+                body.execute_shell_command(session)
+            }
+            ShellCommand::Subshell(body) => {
+                if input.is_some() {
+                    return Err(ShellError::PipeInputNotAccepted(self.clone()));
+                }
+                let mut subshell = session.subshell();
+                let result = body.execute_shell_command(&mut subshell);
+                session.set_status(subshell.status());
+                result
+            }
+            ShellCommand::Redirect(c, path, append) => {
+                let path = ShellCommand::redirect_path(path, session)?;
+                let result =
+                    session.captured(|session| c.execute_with_input(input, lossy, session));
+                let (output, mut errors) = match result {
+                    Ok(output) => (output, vec![]),
+                    Err(ShellError::PartialFailure(output, e)) => (output, vec![*e]),
+                    // Like in other shells the file is written even so.
+                    Err(e) => (CommandOutput::None, vec![e]),
+                };
+                if let Err(e) = tee(output, &[path], *append) {
+                    let e = match e {
+                        ShellError::PartialFailure(_, e) => *e,
+                        e => e,
+                    };
+                    session.set_status(e.status());
+                    errors.push(e);
+                }
+                match errors.len() {
+                    0 => Ok(CommandOutput::None),
+                    1 => Err(errors.remove(0)),
+                    _ => Err(ShellError::MultipleErrors(errors)),
+                }
+            }
+            ShellCommand::Assign(name, value) => {
+                if input.is_some() {
+                    return Err(ShellError::PipeInputNotAccepted(self.clone()));
+                }
+                let value = session
+                    .expand(value)
+                    .inspect_err(|e| session.set_status(e.status()))?;
+                // Quotes are removed, the value is not split into words.
+                let value = split_quoted(&value).unwrap_or_default().join(" ");
+                session.set_var(name, value);
+                session.set_status(0);
+                Ok(CommandOutput::None)
+            }
+        }
+    }
+
+    /// The file a `Redirect` writes to, once expanded, relative to the
+    /// current directory of `session`.
+    fn redirect_path(path: &str, session: &mut Session) -> Result<PathBuf, ShellError> {
+        let expanded = session
+            .expand(path)
+            .inspect_err(|e| session.set_status(e.status()))?;
+        match split_quoted(&expanded).as_deref() {
+            Some([path]) => Ok(session.resolve(path)),
+            _ => {
+                let e = ShellError::UnexpectedToken(expanded, None);
+                session.set_status(e.status());
+                Err(e)
+            }
+        }
+    }
+
+    /// Parses the text of a `Deferred` command once expanded: a call if it
+    /// starts with the name of a function of `session`, a command of its
+    /// registry otherwise.
+    fn parse_expanded(text: &str, session: &Session) -> Result<ShellCommand, ShellError> {
+        let name = text.split(' ').next().unwrap_or_default();
+        if session.function(name).is_none() {
+            return ShellCommand::parse_single_command(text, session.registry());
+        }
+        let mut words = split_quoted(text).ok_or_else(|| session.registry().wrong_args(text))?;
+        let args = words.split_off(1);
+        Ok(ShellCommand::Call(name.to_string(), args))
+    }
 
-                // Look to see if this is a known command with the wrong # of arguments.
-                let known_commands = [
-                    "more",
-                    "cat",
-                    "ls",
-                    "cd",
-                    "find-string",
-                    "subs-string",
-                    "find-file",
-                ];
-                // Step #17
-                // Check if this is a known command, if so return
-                // KnownCommandWrongArgs
-
-                // Step #18
-                // Else return UnknownCommand
-                unimplemented!()
+    /// The command whose output ends up printed: the last one of a pipe.
+    /// For a line run in the background, the last one of that line.
+    /// None for statements and commands only parsed when they run.
+    pub fn last_stage(&self) -> Option<&dyn Command> {
+        match self {
+            ShellCommand::Builtin(c) => Some(c.as_ref()),
+            ShellCommand::Lossy(c) => c.last_stage(),
+            ShellCommand::Pipe(_, c2) => c2.last_stage(),
+            ShellCommand::Background(c, _) => c.last_stage(),
+            _ => None,
+        }
+    }
+
+    /// Status of this command given its `result`, for scripts to check:
+    /// the status of the last command of a pipe, see `Command::status`.
+    /// Starting a job always succeeds, its own status comes when it is done.
+    /// The status of statements is the one of the last command they ran,
+    /// see `Session::status`.
+    pub fn status(&self, result: &Result<CommandOutput, ShellError>) -> i32 {
+        match (self, self.last_stage(), result) {
+            (ShellCommand::Background(..), _, _) => 0,
+            (_, Some(command), _) => command.status(result),
+            (_, None, Ok(_)) => 0,
+            (_, None, Err(e)) => e.status(),
+        }
+    }
+
+    /// How the output of this command is shown on a terminal: the way the
+    /// last command of a pipe prefers, see `Command::presentation`.
+    pub fn presentation(&self) -> Presentation {
+        match (self, self.last_stage()) {
+            (
+                ShellCommand::Pipe(..) | ShellCommand::Builtin(_) | ShellCommand::Lossy(_),
+                Some(c),
+            ) => c.presentation(),
+            _ => Presentation::Plain,
+        }
+    }
+}
+
+/// Output and errors of the statements of a list, e.g. the body of a loop.
+/// Like in other shells a failing statement does not stop the list, unless
+/// it was interrupted or the session exits.
+/// When the session has an output sink, each statement hands its output
+/// and errors to it as it ends and the list returns nothing.
+struct ListOutput {
+    output: CommandOutput,
+    errors: Vec<ShellError>,
+}
+
+impl ListOutput {
+    fn new() -> Self {
+        ListOutput {
+            output: CommandOutput::None,
+            errors: vec![],
+        }
+    }
+
+    /// Adds the result of a statement. Returns whether the list carries on.
+    fn add(&mut self, result: Result<CommandOutput, ShellError>, session: &Session) -> bool {
+        if let Some(sink) = session.output() {
+            let interrupted = matches!(result, Err(ShellError::Interrupted));
+            // Nested lists handed over their output already.
+            if !matches!(result, Ok(CommandOutput::None)) {
+                sink(result, session.settings.output_format);
+            }
+            return !interrupted && session.exit_status().is_none();
+        }
+        let output = match result {
+            Ok(output) => output,
+            Err(ShellError::PartialFailure(output, e)) => {
+                self.errors.push(*e);
+                output
+            }
+            Err(e) => {
+                let interrupted = matches!(e, ShellError::Interrupted);
+                self.errors.push(e);
+                if interrupted {
+                    return false;
                 }
+                CommandOutput::None
+            }
+        };
+        let previous = std::mem::replace(&mut self.output, CommandOutput::None);
+        self.output = previous.append(output);
+        session.exit_status().is_none()
+    }
+
+    /// The output of every statement, with their errors if any failed.
+    fn finish(mut self) -> Result<CommandOutput, ShellError> {
+        let error = match self.errors.len() {
+            0 => return Ok(self.output),
+            1 => self.errors.remove(0),
+            _ => ShellError::MultipleErrors(self.errors),
+        };
+        match self.output {
+            CommandOutput::None => Err(error),
+            output => Err(ShellError::PartialFailure(output, Box::new(error))),
         }
     }
+}
 
+/// Result of a pipe stage run on the output of an earlier stage that
+/// failed with `error`: the output is kept and the errors are combined.
+fn after_partial_failure(
+    error: ShellError,
+    result: Result<CommandOutput, ShellError>,
+) -> Result<CommandOutput, ShellError> {
+    match result {
+        Ok(output) => Err(ShellError::PartialFailure(output, Box::new(error))),
+        Err(ShellError::PartialFailure(output, e)) => Err(ShellError::PartialFailure(
+            output,
+            Box::new(ShellError::MultipleErrors(vec![error, *e])),
+        )),
+        Err(e) => Err(ShellError::MultipleErrors(vec![error, e])),
+    }
+}
 
-    /// Take the command, and call the corresponding commands::function
-    /// for the command.
-    /// Handles piped commands by piping their input together.
-    pub fn execute_shell_command(&self) -> Result<CommandOutput, ShellError> {
-        // Match on the ShellCommand calling the right function from command.
-        // Ignore pipes from now. Save them until you have single commands working!
-        // Step #19
-        unimplemented!()
+/// Splits `s` into words separated by spaces. Text in single or double
+/// quotes is a single word, spaces included, and `\"` is a quote inside
+/// double quotes. Returns None if a quote is not closed.
+pub fn split_quoted(s: &str) -> Option<Vec<String>> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' if chars.as_str().starts_with('"') => {
+                            chars.next();
+                            word.push('"');
+                        }
+                        c => word.push(c),
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Some(words)
+}
+
+/// Head and tail read files or pipe input, tail also follows files.
+#[test]
+fn parse_head_tail_test() {
+    let session = Session::new();
+    let parse = |s| session.parse(s).map(|c| format!("{:?}", c));
+    assert_eq!(parse("head").unwrap(), "Head([], 10)");
+    assert_eq!(parse("head -n 3 a b").unwrap(), r#"Head(["a", "b"], 3)"#);
+    assert_eq!(parse("tail -f -n2 a").unwrap(), r#"Tail(["a"], 2, true)"#);
+    assert!(parse("tail -f").is_err());
+    assert!(parse("head -f a").is_err());
+    assert!(parse("head -n x a").is_err());
+}
+
+/// Output is piped through every command of a longer pipe.
+#[test]
+fn pipe_head_tail_test() {
+    let mut session = Session::new();
+    let input = "cat resources/cat_file_test.txt | head -n 2 | tail -n 1";
+    let command = session.parse(input).unwrap();
+    let output = session.execute(&command).unwrap().to_string();
+    assert_eq!(output, Some("is".to_string()));
+}
+
+/// Flags of the text processing commands.
+#[test]
+fn parse_text_commands_test() {
+    let session = Session::new();
+    let parse = |s| session.parse(s).map(|c| format!("{:?}", c));
+    assert_eq!(
+        parse("sort -nr -k 2").unwrap(),
+        "Sort([], SortOptions { numeric: true, reverse: true, key: Some(2), unique: false })"
+    );
+    assert_eq!(
+        parse(r"cut -d \s -f 1,3- a").unwrap(),
+        r#"Cut(["a"], ' ', [(1, 1), (3, 18446744073709551615)])"#
+    );
+    assert_eq!(parse(r"tr -d \n").unwrap(), r#"Tr("\n", None)"#);
+    assert!(parse("cut -d ab -f 1").is_err());
+    assert!(parse("cut a").is_err());
+    assert!(parse("sort -k 0").is_err());
+}
+
+/// Text processing commands work in the middle of a pipe.
+#[test]
+fn pipe_text_commands_test() {
+    let mut session = Session::new();
+    let input = "cut -d \\s -f 3 resources/fruits.txt | sort | uniq -c | sort -nr -k 1 | head -n 1";
+    let command = session.parse(input).unwrap();
+    let output = session.execute(&command).unwrap().to_string();
+    assert_eq!(output, Some("3\tred".to_string()));
+}
+
+/// File manipulation commands need paths, cp and mv need a destination.
+#[test]
+fn parse_file_commands_test() {
+    let session = Session::new();
+    let parse = |s| session.parse(s).map(|c| format!("{:?}", c));
+    assert_eq!(
+        parse("cp -r a b c").unwrap(),
+        r#"Cp(["a", "b"], "c", true)"#
+    );
+    assert_eq!(
+        parse("rm -rf --no-preserve-root /").unwrap(),
+        r#"Rm(["/"], RmOptions { recursive: true, force: true, no_preserve_root: true })"#
+    );
+    assert_eq!(parse("mkdir -p a/b").unwrap(), r#"Mkdir(["a/b"], true)"#);
+    assert!(parse("mv a").is_err());
+    assert!(parse("touch").is_err());
+    assert!(parse("mkdir -r a").is_err());
+    assert_eq!(parse("dry-run on").unwrap(), "SetDryRun(true)");
+    assert_eq!(parse("tree -L 2 src").unwrap(), r#"Tree("src", Some(2))"#);
+    assert_eq!(
+        parse("du -sh").unwrap(),
+        r#"Du(".", DuOptions { summarize: true, human: true })"#
+    );
+    assert!(parse("tree -L 0").is_err());
+    assert_eq!(parse("restore").unwrap(), "Restore(None)");
+    assert!(matches!(
+        parse("trash maybe"),
+        Err(ShellError::KnownCommandWrongArgs(..))
+    ));
+}
+
+/// `diff` compares pipe input with a file and reports differences in its
+/// status.
+#[test]
+fn diff_status_test() {
+    let registry = Registry::with_builtins();
+    let mut session = Session::new();
+    let parse = |s| ShellCommand::create_shell_command(s, &registry).unwrap();
+    assert_eq!(
+        format!("{:?}", parse("diff -U 1 a b")),
+        r#"Diff("a", "b", 1)"#
+    );
+    assert!(session.parse("diff a").is_err());
+    // Both sides cannot come from the one pipe input.
+    assert!(matches!(
+        session.parse("echo a | diff - -"),
+        Err(ShellError::KnownCommandWrongArgs(..))
+    ));
+
+    let same = parse("cat resources/fruits.txt | diff - resources/fruits.txt");
+    let result = session.execute(&same);
+    assert!(matches!(result, Ok(CommandOutput::None)));
+    assert_eq!(same.status(&result), 0);
+
+    let different = parse("head -n 2 resources/fruits.txt | diff resources/fruits.txt -");
+    let result = session.execute(&different);
+    assert!(result
+        .as_ref()
+        .unwrap()
+        .lines()
+        .contains(&"-date 1 brown".to_string()));
+    assert_eq!(different.status(&result), 1);
+
+    let missing = parse("diff resources/missing.txt resources/fruits.txt");
+    assert_eq!(missing.status(&session.execute(&missing)), 2);
+}
+
+/// Binary pipe input survives a round trip through hexdump or xxd.
+#[test]
+fn pipe_hexdump_test() {
+    let mut session = Session::new();
+    for input in [
+        "cat resources/binary_file.bin | hd | hexdump -r",
+        "cat resources/binary_file.bin | xxd | xxd -r",
+    ] {
+        let command = session.parse(input).unwrap();
+        match session.execute(&command).unwrap() {
+            CommandOutput::Bytes(bytes) => {
+                assert_eq!(
+                    bytes,
+                    crate::commands::read_file("resources/binary_file.bin").unwrap()
+                )
+            }
+            output => panic!("expected bytes, got {:?}", output),
+        }
+    }
+    assert!(session.parse("hexdump -r -n 4").is_err());
+}
+
+/// Hashing pipe input gives the same hash as hashing the file.
+#[test]
+fn pipe_checksum_test() {
+    let mut session = Session::new();
+    let mut hash = |input| {
+        let command = session.parse(input).unwrap();
+        match session.execute(&command).unwrap() {
+            CommandOutput::Table(_, rows) => rows[0][0].clone(),
+            output => panic!("expected a table, got {:?}", output),
+        }
+    };
+    assert_eq!(
+        hash("cat resources/binary_file.bin | sha256sum"),
+        hash("sha256sum resources/binary_file.bin")
+    );
+    assert_eq!(hash("md5sum resources/fruits.txt").len(), 32);
+    assert!(session.parse("stat").is_err());
+}
+
+/// Literal text from echo, printf and here-strings feeds pipes.
+#[test]
+fn pipe_literal_text_test() {
+    let mut session = Session::new();
+    let mut run = |input| {
+        let command = session.parse(input).unwrap();
+        session.execute(&command).unwrap().to_string()
+    };
+    assert_eq!(
+        run("echo   a  'b  c' \"d\\\"\""),
+        Some("a b  c d\"\n".to_string())
+    );
+    assert_eq!(run("echo -ne a\\tb"), Some("a\tb".to_string()));
+    assert_eq!(run("echo -x"), Some("-x\n".to_string()));
+    assert_eq!(
+        run("printf '%s|%s\\n' x y z | find-string z"),
+        Some("z|".to_string())
+    );
+    assert_eq!(run("wc -l <<< 'one two'"), Some("1".to_string()));
+    assert_eq!(
+        run("tr a-z A-Z <<< hi | subs-string I O"),
+        Some("HO\n".to_string())
+    );
+    assert_eq!(run("echo '<<<' \"a<<<b\""), Some("<<< a<<<b\n".to_string()));
+    assert_eq!(run("tr a-z A-Z <<<'x <<< y'"), Some("X <<< Y\n".to_string()));
+    assert!(session.parse("echo 'open").is_err());
+}
+
+/// Later stages still run after tee fails to write a file, and their
+/// output is kept along with the error.
+#[test]
+fn pipe_tee_failure_test() {
+    let mut session = Session::new();
+    let input = "echo hi | tee /nonexistent/dir/out.txt | tr a-z A-Z | find-string H";
+    let command = session.parse(input).unwrap();
+    match session.execute(&command) {
+        Err(ShellError::PartialFailure(output, _)) => {
+            assert_eq!(output.to_string(), Some("HI".to_string()))
+        }
+        result => panic!("expected a partial failure, got {:?}", result),
+    }
+
+    let input = "echo hi | tee /nonexistent/dir/out.txt | cd x";
+    let command = session.parse(input).unwrap();
+    match session.execute(&command) {
+        Err(ShellError::MultipleErrors(errors)) => assert_eq!(errors.len(), 2),
+        result => panic!("expected both errors, got {:?}", result),
+    }
+}
+
+/// Every command the parser knows has a usage line to show when it gets
+/// the wrong arguments.
+#[test]
+fn wrong_args_usage_test() {
+    let session = Session::new();
+    for command in [
+        "cat",
+        "cd",
+        "cut",
+        "tee",
+        "restore a b",
+        "help a b",
+        "xxd -q",
+    ] {
+        match session.parse(command) {
+            Err(e @ ShellError::KnownCommandWrongArgs(..)) => {
+                assert!(e.to_string().contains("\nusage: "), "{}", e)
+            }
+            result => panic!("expected wrong arguments, got {:?}", result),
+        }
     }
+    assert!(matches!(
+        session.parse("type"),
+        Err(ShellError::KnownCommandWrongArgs(..))
+    ));
 }
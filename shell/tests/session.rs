@@ -0,0 +1,632 @@
+//! Drives the shell library the way embedding programs do, against the
+//! fixtures in `resources/`.
+
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use shell::command::{Command, CommandInfo, Context, PipeInput};
+use shell::{CommandOutput, OutputFormat, Session, ShellError};
+
+/// Text output of a line run in a new session.
+fn run(line: &str) -> String {
+    let output = Session::new().run(line).unwrap();
+    output.to_string().unwrap()
+}
+
+/// Files are read and piped through text commands.
+#[test]
+fn pipe_fixture_test() {
+    assert_eq!(run("cat resources/cat_file_test.txt"), "omar\nis\ncool\n");
+    assert_eq!(
+        run("sort -u resources/fruits.txt | cut -d \\s -f 1 | head -n 2"),
+        "apple\nbanana"
+    );
+    assert_eq!(
+        run("find-string resources/fruits.txt txt"),
+        "resources/fruits.txt"
+    );
+}
+
+/// Lists such as the output of `ls` and `find-file` reach the next command
+/// of a pipe as one line per entry.
+#[test]
+fn pipe_list_test() {
+    assert_eq!(
+        run("ls resources | sort"),
+        "resources/binary_file.bin\nresources/cat_file_test.txt\nresources/fruits.txt\n\
+         resources/test_dir"
+    );
+    assert_eq!(run("ls resources | wc -l"), "4");
+    assert_eq!(
+        run("find-file dummy.txt resources | sort -r | head -n 1"),
+        "resources/test_dir/test_dir2/dummy.txt"
+    );
+    assert_eq!(
+        run("find-file dummy.txt resources | head -n 1")
+            .lines()
+            .count(),
+        1
+    );
+}
+
+/// Arguments are split on spaces outside quotes, quotes are removed and
+/// runs of spaces separate words like one space.
+#[test]
+fn quoted_arguments_test() {
+    let dir = std::env::temp_dir().join("shell_quoted_arguments_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a b.txt"), "spaced\n").unwrap();
+    let mut session = Session::new();
+    session.set_cwd(&dir);
+    assert_eq!(text(&mut session, "cat \"a b.txt\""), "spaced\n");
+    assert_eq!(text(&mut session, "x=\"a b.txt\"; cat \"$x\""), "spaced\n");
+    assert_eq!(text(&mut session, "cat  'a b.txt'  "), "spaced\n");
+    assert_eq!(run("cat  resources/cat_file_test.txt"), "omar\nis\ncool\n");
+    assert_eq!(run("find-string 'a b\nc' 'a b'"), "a b");
+}
+
+/// Structured output keeps its shape, ready to be rendered in any format.
+#[test]
+fn structured_output_test() {
+    let mut session = Session::new();
+    match session.run("wc -l resources/fruits.txt").unwrap() {
+        CommandOutput::Table(columns, rows) => {
+            assert_eq!(columns, vec!["lines", "file"]);
+            assert_eq!(rows, vec![vec!["5", "resources/fruits.txt"]]);
+        }
+        output => panic!("expected a table, got {:?}", output),
+    }
+    match session.run("ls resources/test_dir").unwrap() {
+        CommandOutput::List(entries) => assert_eq!(entries.len(), 2),
+        output => panic!("expected a list, got {:?}", output),
+    }
+}
+
+/// Parse and runtime errors come back as values, the session keeps working.
+#[test]
+fn errors_test() {
+    let mut session = Session::new();
+    assert!(matches!(
+        session.run("no-such-command"),
+        Err(ShellError::UnknownCommand(..))
+    ));
+    assert!(matches!(
+        session.run("head -n x"),
+        Err(ShellError::KnownCommandWrongArgs(..))
+    ));
+    assert!(session.run("cat resources/missing.txt").is_err());
+    assert!(session.run("cat resources/fruits.txt").is_ok());
+}
+
+/// Counts the lines of its pipe input.
+#[derive(Debug, Clone)]
+struct CountLines;
+
+impl Command for CountLines {
+    fn info() -> &'static CommandInfo {
+        &CommandInfo {
+            name: "count-lines",
+            aliases: &[],
+            args: "",
+            summary: "Count the lines of pipe input",
+            flags: &[],
+        }
+    }
+
+    fn parse(args: &[&str]) -> Option<Self> {
+        match args {
+            [] => Some(CountLines),
+            _ => None,
+        }
+    }
+
+    fn pipe_input(&self) -> PipeInput {
+        PipeInput::Required
+    }
+
+    fn execute(&self, context: &mut Context) -> Result<CommandOutput, ShellError> {
+        let lines = context.text_input(self)?.lines().count();
+        Ok(CommandOutput::Single(lines.to_string()))
+    }
+}
+
+/// Commands defined outside the library run next to the built-ins and
+/// show up in `help`.
+#[test]
+fn custom_command_test() {
+    let mut session = Session::new();
+    session.registry_mut().register::<CountLines>();
+    let output = session
+        .run("cat resources/fruits.txt | count-lines")
+        .unwrap();
+    assert_eq!(output.to_string(), Some("5".to_string()));
+    let help = session
+        .run("help count-lines")
+        .unwrap()
+        .to_string()
+        .unwrap();
+    assert!(help.contains("Count the lines of pipe input"));
+}
+
+/// `cd` only moves its own session, relative paths of the other sessions
+/// and of the process stay where they were.
+#[test]
+fn session_cwd_test() {
+    let mut moved = Session::new();
+    let mut other = Session::new();
+    moved.run("cd resources/test_dir/../").unwrap();
+    assert_eq!(moved.cwd(), std::path::Path::new("resources"));
+    assert!(moved.run("cat fruits.txt").is_ok());
+    assert!(moved.run("find-file dummy.txt test_dir").is_ok());
+    assert!(other.run("cat fruits.txt").is_err());
+    assert!(std::path::Path::new("resources/fruits.txt").exists());
+
+    moved.run("cd ..").unwrap();
+    assert_eq!(moved.cwd(), std::path::Path::new("."));
+    assert!(moved.run("cd resources/fruits.txt").is_err());
+}
+
+/// Settings such as the output format belong to each session.
+#[test]
+fn session_settings_test() {
+    let mut json = Session::new();
+    json.run("output-format json").unwrap();
+    json.run("dry-run on").unwrap();
+    assert_eq!(json.settings.output_format, OutputFormat::Json);
+    assert!(json.settings.change_mode.dry_run);
+    assert_eq!(Session::new().settings.output_format, OutputFormat::Text);
+}
+
+/// Every command line leaves a status, `$?` expands to it and `exit` ends
+/// the session with it.
+#[test]
+fn status_test() {
+    let mut session = Session::new();
+    assert_eq!(session.status(), 0);
+    assert!(session.run("cat resources/missing.txt").is_err());
+    assert_eq!(session.status(), 1);
+    let output = session.run("echo $? \"$?\" '$?'").unwrap();
+    assert_eq!(output.to_string().unwrap(), "1 1 $?\n");
+    assert_eq!(session.status(), 0);
+    assert!(session.run("frobnicate").is_err());
+    assert_eq!(session.status(), 127);
+    assert!(session.run("head -n").is_err());
+    assert_eq!(session.status(), 2);
+    assert!(session
+        .run("diff resources/fruits.txt resources/cat_file_test.txt")
+        .is_ok());
+    assert_eq!(session.status(), 1);
+
+    assert_eq!(session.exit_status(), None);
+    session.run("exit").unwrap();
+    assert_eq!(session.exit_status(), Some(1));
+    let mut session = Session::new();
+    session.run("exit 3").unwrap();
+    assert_eq!((session.exit_status(), session.status()), (Some(3), 3));
+    assert!(Session::new().run("exit three").is_err());
+}
+
+/// Lines ending with `&` run as jobs in a copy of the session and are
+/// collected once done.
+#[test]
+fn jobs_test() {
+    let mut session = Session::new();
+    let output = session.run("cd resources & ").unwrap();
+    assert_eq!(output.to_string().unwrap(), "[1]");
+    session.run("tail -n 1 -f resources/fruits.txt &").unwrap();
+    session.run("wait %1").unwrap();
+    assert_eq!(session.cwd(), std::path::Path::new("."));
+
+    let finished = session.jobs_mut().finished();
+    assert_eq!(finished.len(), 1);
+    assert_eq!((finished[0].0, finished[0].1.as_str()), (1, "cd resources"));
+    assert_eq!(
+        session.jobs().list()[0].line,
+        "tail -n 1 -f resources/fruits.txt"
+    );
+
+    session.run("kill %2").unwrap();
+    assert!(matches!(session.run("fg"), Err(ShellError::Interrupted)));
+    assert_eq!(session.status(), 130);
+    assert!(session.jobs().list().is_empty());
+    assert!(matches!(
+        session.run("fg %2"),
+        Err(ShellError::NoSuchJob(_))
+    ));
+}
+
+/// The output of `$(...)` and backticks takes their place in the command
+/// line, split into words unless in double quotes.
+#[test]
+fn substitution_test() {
+    let mut session = Session::new();
+    let mut run = |line| {
+        session
+            .run(line)
+            .map(|output| output.to_string().unwrap_or_default())
+    };
+    assert_eq!(
+        run("echo $(head -n 2 resources/fruits.txt) end").unwrap(),
+        "banana 3 yellow apple 10 red end\n"
+    );
+    assert_eq!(
+        run("echo \"$(head -n 2 resources/fruits.txt)\"").unwrap(),
+        "banana 3 yellow\napple 10 red\n"
+    );
+    assert_eq!(run("echo '$(nope)' `echo -n hi`").unwrap(), "$(nope) hi\n");
+    assert_eq!(run("echo $(echo $(echo 'a | b'))").unwrap(), "a | b\n");
+    assert_eq!(
+        run("wc -l $(find-file fruits.txt resources)").unwrap(),
+        "5\tresources/fruits.txt"
+    );
+    assert!(matches!(
+        run("echo $(echo"),
+        Err(ShellError::UnclosedSubstitution(..))
+    ));
+    assert!(run("cat $(cat resources/missing.txt)").is_err());
+
+    // The output of a pipe substitutes as the lines it printed.
+    assert_eq!(
+        run("echo $(find-file dummy.txt resources | head -n 1)").unwrap(),
+        "resources/test_dir/dummy.txt\n"
+    );
+    // Only the first match is passed to cd, which then refuses a file.
+    match run("cd $(find-file Cargo.toml . | head -n 1)") {
+        Err(ShellError::PathError(path, _)) => {
+            assert_eq!(path, std::path::Path::new("./Cargo.toml"))
+        }
+        result => panic!("expected cd to fail on ./Cargo.toml, got {:?}", result),
+    }
+    run("cd $(find-file test_dir2 resources | head -n 1)").unwrap();
+    assert_eq!(
+        session.cwd(),
+        std::path::Path::new("resources/test_dir/test_dir2")
+    );
+}
+
+/// `<(...)` hands the output of a command line over as a path, so two
+/// outputs can be compared without saving them to files.
+#[test]
+fn process_substitution_test() {
+    let mut session = Session::new();
+    let fruits = "resources/fruits.txt";
+    let line = format!("diff <(head -n 2 {0}) <(head -n 3 {0} | tail -n 2)", fruits);
+    let output = session.run(&line).unwrap().to_string().unwrap();
+    assert!(output.starts_with("--- /dev/fd/"), "{}", output);
+    assert!(output.ends_with("-banana 3 yellow\n apple 10 red\n+cherry 7 red"));
+    assert_eq!(session.status(), 1);
+
+    let line = format!("cat {0} | diff - <(cat {0})", fruits);
+    assert!(matches!(session.run(&line), Ok(CommandOutput::None)));
+    assert_eq!(session.status(), 0);
+    let line = format!("diff <(cat {0} {0}) <(cat {0}) | wc -l", fruits);
+    assert_eq!(text(&mut session, &line), "10");
+    assert_eq!(
+        text(&mut session, "echo '<(nope)' \"<(nope)\""),
+        "<(nope) <(nope)\n"
+    );
+    assert!(matches!(
+        session.run("diff a <(echo"),
+        Err(ShellError::UnclosedSubstitution(..))
+    ));
+}
+
+/// Output of a line run in `session`, including the output kept when some
+/// of its commands failed.
+fn text(session: &mut Session, line: &str) -> String {
+    match session.run(line) {
+        Ok(output) | Err(ShellError::PartialFailure(output, _)) => {
+            output.to_string().unwrap_or_default()
+        }
+        Err(e) => panic!("expected output from {}, got {:?}", line, e),
+    }
+}
+
+/// `if`, `for` and `while` run their bodies depending on the status of
+/// their conditions, on one line or several.
+#[test]
+fn control_flow_test() {
+    let mut session = Session::new();
+    let same = "diff resources/fruits.txt resources/fruits.txt";
+    let missing = "cd resources/missing";
+    assert_eq!(
+        text(
+            &mut session,
+            &format!("if {}; then echo yes; else echo no; fi", same)
+        ),
+        "yes\n"
+    );
+    let line = format!(
+        "if {}; then echo a; elif {}; then echo b; fi",
+        missing, same
+    );
+    assert_eq!(text(&mut session, &line), "b\n");
+    assert_eq!(session.status(), 0);
+    let line = format!("if {}\nthen\n  echo a\nelse\n  echo c\nfi", missing);
+    assert_eq!(text(&mut session, &line), "c\n");
+
+    assert_eq!(
+        text(
+            &mut session,
+            "for x in a 'b c' $(echo d e); do echo -n $x,; done"
+        ),
+        "a,b c,d,e,"
+    );
+    assert_eq!(
+        text(&mut session, "for x in 1 2 # numbers\ndo\n  echo $x\ndone"),
+        "1\n2\n"
+    );
+
+    let dir = std::env::temp_dir().join("shell_control_flow_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    session.set_cwd(&dir);
+    session.run("touch a b c").unwrap();
+    let line = "while cat a; do rm a; echo removed; done";
+    assert_eq!(text(&mut session, line), "removed\n");
+    assert_eq!(session.status(), 0);
+    assert!(!dir.join("a").exists() && dir.join("b").exists());
+
+    for line in ["if ls; then echo", "for x in a b; do", "while ls"] {
+        assert!(matches!(
+            session.run(line),
+            Err(ShellError::UnexpectedEnd(..))
+        ));
+    }
+    for line in [
+        "done",
+        "if ls; fi",
+        "for 1 in a; do echo; done",
+        "echo a && echo b",
+    ] {
+        assert!(matches!(
+            session.run(line),
+            Err(ShellError::UnexpectedToken(..))
+        ));
+        assert_eq!(session.status(), 2);
+    }
+}
+
+/// Functions take their arguments as `$1`, `$2`... `$#` and `$@`, can
+/// call themselves and are called like other commands.
+#[test]
+fn functions_test() {
+    let mut session = Session::new();
+    session.run("greet() { echo hello $1 of $#: $@; }").unwrap();
+    assert_eq!(
+        text(&mut session, "greet world 'a b'"),
+        "hello world of 2: world a b\n"
+    );
+    assert_eq!(text(&mut session, "greet | tr a-z A-Z"), "HELLO OF 0:\n");
+    assert_eq!(session.args(), &[] as &[String]);
+
+    let script =
+        "countdown() {\n  for n; do echo $n; done\n  if diff $2 $2; then countdown $2; fi\n}";
+    session.run(script).unwrap();
+    assert_eq!(
+        text(&mut session, "countdown 3 resources/fruits.txt"),
+        "3\nresources/fruits.txt\nresources/fruits.txt\n"
+    );
+
+    session.run("each() { for x in $@; do $x; done; }").unwrap();
+    assert!(matches!(
+        session.run("each no-such-command"),
+        Err(ShellError::UnknownCommand(..))
+    ));
+    assert_eq!(session.status(), 127);
+    assert!(matches!(
+        session.run("echo a | greet"),
+        Err(ShellError::PipeInputNotAccepted(_))
+    ));
+    assert!(matches!(
+        session.run("f() { exit 3; echo never; }; f; echo never"),
+        Ok(CommandOutput::None)
+    ));
+    assert_eq!(session.exit_status(), Some(3));
+}
+
+/// With an output sink, statements hand over their output and errors as
+/// they end, in order, except for output going into a pipe or a file.
+#[test]
+fn output_sink_test() {
+    let printed = Arc::new(Mutex::new(vec![]));
+    let sink = printed.clone();
+    let mut session = Session::new();
+    session.set_output(Some(Arc::new(move |result, _| {
+        let line = match result {
+            Ok(output) => output.to_string().unwrap_or_default(),
+            Err(e) => format!("error {}", e.status()),
+        };
+        sink.lock().unwrap().push(line);
+    })));
+
+    let output = session.run("cat missing.txt; echo $?; for x in a b; do echo $x; done");
+    assert!(matches!(output, Ok(CommandOutput::None)));
+    assert_eq!(*printed.lock().unwrap(), ["error 1", "1\n", "a\n", "b\n"]);
+
+    printed.lock().unwrap().clear();
+    assert_eq!(text(&mut session, "{ echo a; echo b; } | wc -l"), "2");
+    assert_eq!(text(&mut session, "echo $(echo a; echo b)"), "a b\n");
+    assert!(printed.lock().unwrap().is_empty());
+}
+
+/// `tail -f` hands lines to the sink as they are appended, it cannot be
+/// piped, redirected or substituted.
+#[test]
+fn tail_follow_test() {
+    let dir = std::env::temp_dir().join("shell_tail_follow_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("log.txt");
+    std::fs::write(&path, "a\nb\n").unwrap();
+
+    let mut session = Session::new();
+    for line in [
+        "tail -f {} | wc -l",
+        "tail -f {} > {}.out",
+        "echo $(tail -f {})",
+    ] {
+        let line = line.replace("{}", path.to_str().unwrap());
+        assert!(
+            matches!(session.run(&line), Err(ShellError::NoInputForPipe(_))),
+            "{}",
+            line
+        );
+    }
+
+    let printed = Arc::new(Mutex::new(vec![]));
+    let sink = printed.clone();
+    let token = session.token().clone();
+    let log = path.clone();
+    session.set_output(Some(Arc::new(move |result, _| {
+        let mut printed = sink.lock().unwrap();
+        printed.push(result.unwrap().lines());
+        match printed.len() {
+            // Half a line is only handed over once it ends.
+            1 => {
+                append(&log, "c\nd");
+                let log = log.clone();
+                std::thread::spawn(move || {
+                    std::thread::sleep(std::time::Duration::from_millis(600));
+                    append(&log, "\n");
+                });
+            }
+            3 => token.cancel(),
+            _ => {}
+        }
+    })));
+    let line = format!("tail -n 1 -f {}", path.display());
+    assert!(matches!(session.run(&line), Err(ShellError::Interrupted)));
+    assert_eq!(*printed.lock().unwrap(), [["b"], ["c"], ["d"]]);
+}
+
+fn append(path: &std::path::Path, text: &str) {
+    let mut file = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+    file.write_all(text.as_bytes()).unwrap();
+}
+
+/// Statements in `( )` or `{ }` combine their output for a pipe or a
+/// file, only `{ }` keeps changes to the session.
+#[test]
+fn grouping_test() {
+    let dir = std::env::temp_dir().join("shell_grouping_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut session = Session::new();
+    let line = "( cd resources; cat fruits.txt; echo apple pie ) | find-string apple";
+    assert_eq!(
+        session.run(line).unwrap().lines(),
+        vec!["apple 10 red", "apple 10 red", "apple pie"]
+    );
+    assert_eq!(session.cwd(), std::path::Path::new("."));
+    // Text and lists of different commands are piped as lines.
+    assert_eq!(
+        text(&mut session, "( echo a; ls resources/test_dir ) | wc -l"),
+        "3"
+    );
+    assert_eq!(
+        text(
+            &mut session,
+            "{ echo b; ls resources/test_dir; echo a; } | sort"
+        ),
+        "a\nb\nresources/test_dir/dummy.txt\nresources/test_dir/test_dir2"
+    );
+
+    session.set_cwd(&dir);
+    session.run("{ echo one; echo two; } > out.txt").unwrap();
+    session.run("echo three >> out.txt").unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join("out.txt")).unwrap(),
+        "one\ntwo\nthree\n"
+    );
+    assert!(matches!(
+        session.run("for x in a b; do echo $x; done > out.txt"),
+        Ok(CommandOutput::None)
+    ));
+    assert_eq!(text(&mut session, "cat out.txt"), "a\nb\n");
+
+    session.run("{ cd ..; }").unwrap();
+    assert_eq!(session.cwd(), std::env::temp_dir());
+    session.run("(exit 4)").unwrap();
+    assert_eq!(session.status(), 4);
+    assert_eq!(session.exit_status(), None);
+
+    assert!(session.run("echo a > /nonexistent/dir/out.txt").is_err());
+    assert_eq!(session.status(), 1);
+    for line in ["echo a >", "(echo a)(echo b)", "echo )", "echo a; }"] {
+        assert!(matches!(
+            session.run(line),
+            Err(ShellError::UnexpectedToken(..))
+        ));
+    }
+    assert!(matches!(
+        session.run("( echo a"),
+        Err(ShellError::UnexpectedEnd(..))
+    ));
+}
+
+/// `name=value` sets a variable of the session, expanded once and with
+/// its quotes removed. `( )` keeps it to itself.
+#[test]
+fn assignment_test() {
+    let mut session = Session::new();
+    session.run("greeting='hello  world'").unwrap();
+    assert_eq!(session.var("greeting"), Some("hello  world"));
+    session.run("both=\"$greeting $(echo -n again)\"").unwrap();
+    assert_eq!(session.var("both"), Some("hello  world again"));
+    assert_eq!(text(&mut session, "echo \"$both\""), "hello  world again\n");
+
+    session.run("(greeting=bye)").unwrap();
+    assert_eq!(session.var("greeting"), Some("hello  world"));
+    session.run("PROMPT='\\W [\\?] '").unwrap();
+    assert_eq!(session.var("PROMPT"), Some("\\W [\\?] "));
+    assert!(matches!(
+        session.run("x='open"),
+        Err(ShellError::UnexpectedEnd(..))
+    ));
+}
+
+/// The shell prints errors with their message: refusals to remove
+/// protected paths and missing files, not Rust debug output.
+#[test]
+fn printed_errors_test() {
+    let dir = std::env::temp_dir().join("shell_printed_errors_test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("a")).unwrap();
+    let mut shell = std::process::Command::new(env!("CARGO_BIN_EXE_shell"))
+        .current_dir(&dir)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Dry runs only, should the refusals ever break.
+    let input = "dry-run on\nrm -r /\ncd a\nrm -r ..\ncat missing.txt\n";
+    shell
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = shell.wait_with_output().unwrap();
+    let printed = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        printed.contains(
+            "Error: refusing to remove / recursively, use --no-preserve-root to override\n"
+        ),
+        "{}",
+        printed
+    );
+    assert!(
+        printed.contains("Error: a/..: refusing to remove '.' or '..' directory\n"),
+        "{}",
+        printed
+    );
+    assert!(
+        printed.contains("Error: a/missing.txt: No such file or directory"),
+        "{}",
+        printed
+    );
+    assert!(!printed.contains("Error("), "{}", printed);
+    assert!(dir.join("a").is_dir());
+}